@@ -0,0 +1,392 @@
+//! `rlox check`: an optional static type pass. Variables declared with a
+//! type, as in `var x: Number = 1;`, are checked on every assignment, and
+//! operators are checked wherever their operand types are known. An
+//! unannotated variable takes the type of the value last assigned to it,
+//! since the language has no statements that branch.
+
+use crate::grammar::{Expr, Stmt};
+use crate::token::{Literal, Token, TokenType};
+use crate::RloxError;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    /// Unknown until run time. Always accepted.
+    Any,
+    Nil,
+    Bool,
+    Int,
+    /// Any number. Integers are numbers too, so an `Int` fits a `Number`.
+    Number,
+    String,
+    List,
+    Map
+}
+
+const TYPES: &[(&str, Type)] = &[
+    ("Any", Type::Any),
+    ("Nil", Type::Nil),
+    ("Bool", Type::Bool),
+    ("Int", Type::Int),
+    ("Number", Type::Number),
+    ("String", Type::String),
+    ("List", Type::List),
+    ("Map", Type::Map)
+];
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = TYPES.iter().find(|(_, t)| t == self).map_or("Any", |(name, _)| name);
+        write!(f, "{}", name)
+    }
+}
+
+impl Type {
+    fn accepts(self, other: Type) -> bool {
+        self == other || self == Type::Any || other == Type::Any || (self == Type::Number && other == Type::Int)
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Type::Int | Type::Number | Type::Any)
+    }
+
+    fn is_integer(self) -> bool {
+        matches!(self, Type::Int | Type::Any)
+    }
+
+    /// The type of a value that is either `self` or `other`.
+    fn join(self, other: Type) -> Type {
+        if self == other {
+            self
+        } else if self.is_numeric() && other.is_numeric() && self != Type::Any && other != Type::Any {
+            Type::Number
+        } else {
+            Type::Any
+        }
+    }
+}
+
+/// Checks `statements` and returns every type error found.
+pub fn check(statements: &[Stmt]) -> Vec<RloxError> {
+    let mut checker = Checker { scopes: vec![HashMap::new()], errors: Vec::new(), branches: 0 };
+    for stmt in statements {
+        checker.stmt(stmt);
+    }
+    checker.errors
+}
+
+#[derive(Clone, Copy)]
+struct Variable {
+    /// The annotated type, or the type of the last value assigned.
+    value: Type,
+    annotated: bool
+}
+
+struct Checker {
+    /// Every variable in each open scope, globals first.
+    scopes: Vec<HashMap<String, Variable>>,
+    errors: Vec<RloxError>,
+    /// How many branches of conditional expressions are being checked. An
+    /// assignment inside one might not run.
+    branches: usize
+}
+
+impl Checker {
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(statements) => {
+                self.scopes.push(HashMap::new());
+                statements.iter().for_each(|stmt| self.stmt(stmt));
+                self.scopes.pop();
+            },
+            Stmt::Echo(expr) | Stmt::Expression(expr) | Stmt::Print(expr) => {
+                self.expr(expr);
+            },
+            Stmt::Var { name, annotation, initializer } => {
+                let value = initializer.as_ref().map_or(Type::Nil, |expr| self.expr(expr));
+                let declared = match annotation {
+                    Some(annotation) => self.annotation(annotation),
+                    None => value
+                };
+
+                if !declared.accepts(value) {
+                    let message = match initializer {
+                        Some(_) => format!("Can't initialize '{}' of type {} with {}.", name.details().lexeme, declared, value),
+                        None => format!("'{}' of type {} needs an initializer.", name.details().lexeme, declared)
+                    };
+                    self.error(name, &message);
+                }
+                let variable = Variable { value: declared, annotated: annotation.is_some() };
+                self.scopes.last_mut()
+                    .expect("Checker has no scope.")
+                    .insert(name.details().lexeme, variable);
+            }
+        }
+    }
+
+    fn annotation(&mut self, annotation: &Token) -> Type {
+        let lexeme = annotation.details().lexeme;
+        match TYPES.iter().find(|(name, _)| *name == lexeme) {
+            Some((_, t)) => *t,
+            None => {
+                self.error(annotation, &format!("Unknown type '{}'.", lexeme));
+                Type::Any
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Assign { name, operator, value } => {
+                let value = self.expr(value);
+                let declared = self.lookup(name);
+                let result = match operator.details().token_type {
+                    TokenType::PlusEqual => self.binary(operator, TokenType::Plus, declared, value),
+                    TokenType::MinusEqual => self.binary(operator, TokenType::Minus, declared, value),
+                    TokenType::StarEqual => self.binary(operator, TokenType::Star, declared, value),
+                    TokenType::SlashEqual => self.binary(operator, TokenType::Slash, declared, value),
+                    _ => value
+                };
+                self.assign(name, operator, result);
+                result
+            },
+            Expr::Binary { left, operator, right } => {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                self.binary(operator, operator.details().token_type, left, right)
+            },
+            Expr::Call { callee, arguments, .. } => {
+                self.expr(callee);
+                arguments.iter().for_each(|argument| { self.expr(argument); });
+                Type::Any
+            },
+            Expr::Conditional { condition, then_branch, else_branch, .. } => {
+                self.expr(condition);
+                self.branches += 1;
+                let then_type = self.expr(then_branch);
+                let else_type = self.expr(else_branch);
+                self.branches -= 1;
+                then_type.join(else_type)
+            },
+            Expr::Get { object, name } => {
+                // Modules have no type of their own, so only a known type is wrong.
+                let object = self.expr(object);
+                if object != Type::Any {
+                    self.error(name, &format!("Only modules have members, not {}.", object));
+                }
+                Type::Any
+            },
+            Expr::Grouping(inner) => self.expr(inner),
+            Expr::Index { object, bracket, index } => match self.index(object, bracket, index) {
+                // Indexing a string gives a one character string.
+                Type::String => Type::String,
+                _ => Type::Any
+            },
+            Expr::List(elements) => {
+                elements.iter().for_each(|element| { self.expr(element); });
+                Type::List
+            },
+            Expr::Literal(literal) => match literal {
+                Literal::String(_) => Type::String,
+                Literal::Int(_) => Type::Int,
+                Literal::Number(_) => Type::Number,
+                Literal::Nil => Type::Nil,
+                Literal::True | Literal::False => Type::Bool
+            },
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+                Type::Map
+            },
+            Expr::SetIndex { object, bracket, index, operator, value } => {
+                if self.index(object, bracket, index) == Type::String {
+                    self.error(bracket, "Can't assign to an index of String.");
+                }
+                let value = self.expr(value);
+                // The old element of a compound assignment could be anything.
+                if operator.details().token_type == TokenType::Equal { value } else { Type::Any }
+            },
+            Expr::Unary { operator, right } => {
+                let right = self.expr(right);
+                match operator.details().token_type {
+                    TokenType::Minus if !right.is_numeric() => {
+                        self.error(operator, &format!("Operand must be a number, not {}.", right));
+                        Type::Number
+                    },
+                    TokenType::Minus => right,
+                    TokenType::Tilde => {
+                        if !right.is_integer() {
+                            self.error(operator, &format!("Operand must be an integer, not {}.", right));
+                        }
+                        Type::Int
+                    },
+                    _ => Type::Bool
+                }
+            },
+            Expr::Variable(name) => self.lookup(name)
+        }
+    }
+
+    /// Checks that `object[index]` indexes a list or string with an integer,
+    /// or a map with a key that can be hashed, and returns the object's type.
+    fn index(&mut self, object: &Expr, bracket: &Token, index: &Expr) -> Type {
+        let object = self.expr(object);
+        let index = self.expr(index);
+        match object {
+            Type::List | Type::String if !index.is_integer() => {
+                self.error(bracket, &format!("{} index must be an integer, not {}.", object, index));
+            },
+            Type::Map if matches!(index, Type::List | Type::Map) => {
+                self.error(bracket, &format!("A {} can't be a map key.", index));
+            },
+            Type::List | Type::Map | Type::String | Type::Any => (),
+            _ => self.error(bracket, &format!("Can't index {}.", object))
+        }
+        object
+    }
+
+    /// Checks a binary operator the way `eval::apply_binary` applies it and
+    /// returns the result type.
+    fn binary(&mut self, operator: &Token, token_type: TokenType, left: Type, right: Type) -> Type {
+        match token_type {
+            TokenType::Plus => match (left, right) {
+                (Type::String, Type::String) => Type::String,
+                (Type::String, Type::Any) | (Type::Any, Type::String) | (Type::Any, Type::Any) => Type::Any,
+                _ => self.numbers(operator, left, right, "Operands must be two numbers or two strings")
+            },
+            TokenType::Minus | TokenType::Star | TokenType::Slash | TokenType::Percent => {
+                self.numbers(operator, left, right, "Operands must be numbers")
+            },
+            TokenType::StarStar => match self.numbers(operator, left, right, "Operands must be numbers") {
+                // A negative integer exponent gives a float.
+                Type::Int => Type::Any,
+                result => result
+            },
+            TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => {
+                self.numbers(operator, left, right, "Operands must be numbers");
+                Type::Bool
+            },
+            TokenType::Ampersand | TokenType::Pipe | TokenType::Caret | TokenType::LessLess | TokenType::GreaterGreater => {
+                if !left.is_integer() || !right.is_integer() {
+                    self.error(operator, &format!("Operands must be integers, not {} and {}.", left, right));
+                }
+                Type::Int
+            },
+            TokenType::EqualEqual | TokenType::BangEqual => Type::Bool,
+            TokenType::Comma => right,
+            _ => Type::Any
+        }
+    }
+
+    /// Checks arithmetic operands. Two integers give an integer, a float
+    /// operand gives a float, and with an `Any` operand it isn't known.
+    fn numbers(&mut self, operator: &Token, left: Type, right: Type, message: &str) -> Type {
+        if !left.is_numeric() || !right.is_numeric() {
+            self.error(operator, &format!("{}, not {} and {}.", message, left, right));
+            return Type::Number;
+        }
+        match (left, right) {
+            (Type::Int, Type::Int) => Type::Int,
+            (Type::Any, _) | (_, Type::Any) => Type::Any,
+            _ => Type::Number
+        }
+    }
+
+    /// The type of a variable. Names never declared, like the built-ins, are `Any`.
+    fn lookup(&self, name: &Token) -> Type {
+        let lexeme = name.details().lexeme;
+        self.scopes.iter().rev()
+            .find_map(|scope| scope.get(&lexeme))
+            .map_or(Type::Any, |variable| variable.value)
+    }
+
+    /// Checks an assignment of `value` to an annotated variable, or has an
+    /// unannotated one take its type. In a branch that might not run it
+    /// can have either type afterwards.
+    fn assign(&mut self, name: &Token, operator: &Token, value: Type) {
+        let lexeme = name.details().lexeme;
+        let variable = match self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(&lexeme)) {
+            Some(variable) => variable,
+            None => return
+        };
+
+        if !variable.annotated {
+            variable.value = if self.branches > 0 { variable.value.join(value) } else { value };
+        } else if !variable.value.accepts(value) {
+            let message = format!("Can't assign {} to '{}' of type {}.", value, lexeme, variable.value);
+            self.error(operator, &message);
+        }
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        let details = token.details();
+        self.errors.push(RloxError::new(details.line, message, &format!(" at '{}'", details.lexeme)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every error as `line location: message`.
+    fn errors(source: &str) -> Vec<String> {
+        let statements = crate::parse(source.to_string()).unwrap_or_else(|_| panic!("'{}' doesn't parse", source));
+        check(&statements).iter()
+            .map(|err| format!("{}{}: {}", err.line.unwrap_or(0), err.location, err.message))
+            .collect()
+    }
+
+    #[test]
+    fn annotations_are_checked() {
+        assert_eq!(errors("var x: Number = \"a\";"), ["1 at 'x': Can't initialize 'x' of type Number with String."]);
+        assert_eq!(errors("var x: String;"), ["1 at 'x': 'x' of type String needs an initializer."]);
+        assert_eq!(errors("var x: Number = 1;\nx = 2.5;\nx = nil;"), ["3 at '=': Can't assign Nil to 'x' of type Number."]);
+        assert_eq!(errors("var x: Int = 1;\nx /= 2.0;"), ["2 at '/=': Can't assign Number to 'x' of type Int."]);
+        assert!(errors("var x: Any = 1;\nx = \"a\";\nvar y: Nil;").is_empty());
+    }
+
+    #[test]
+    fn unknown_types_are_reported() {
+        assert_eq!(errors("var x: Strng = 1;"), ["1 at 'Strng': Unknown type 'Strng'."]);
+    }
+
+    #[test]
+    fn operators_are_checked() {
+        assert_eq!(errors("\"a\" - 1;"), ["1 at '-': Operands must be numbers, not String and Int."]);
+        assert_eq!(errors("print 1 + \"a\";"), ["1 at '+': Operands must be two numbers or two strings, not Int and String."]);
+        assert_eq!(errors("print 1.5 & 1;"), ["1 at '&': Operands must be integers, not Number and Int."]);
+        assert_eq!(errors("print -\"a\";"), ["1 at '-': Operand must be a number, not String."]);
+        assert_eq!(errors("print [1][\"a\"];"), ["1 at ']': List index must be an integer, not String."]);
+        assert_eq!(errors("print 1.pi;"), ["1 at 'pi': Only modules have members, not Int."]);
+        assert!(errors("print len(\"a\") - 1;\nprint math.pi * 2;").is_empty());
+    }
+
+    #[test]
+    fn unannotated_variables_take_the_type_of_their_value() {
+        assert_eq!(errors("var x = \"a\";\nprint x - 1;"), ["2 at '-': Operands must be numbers, not String and Int."]);
+        assert_eq!(errors("var s = \"a\";\ns -= 1;"), ["2 at '-=': Operands must be numbers, not String and Int."]);
+        assert_eq!(errors("var x;\nprint x + 1;"), ["2 at '+': Operands must be two numbers or two strings, not Nil and Int."]);
+        // Assigning a new value changes the type.
+        assert!(errors("var x = \"a\";\nx = 1;\nprint x - 1;").is_empty());
+        assert_eq!(errors("var x = 1;\nx = \"a\";\nprint x - 1;"), ["3 at '-': Operands must be numbers, not String and Int."]);
+        // Only one branch of a conditional runs.
+        assert!(errors("var x = \"a\";\nvar c = true;\nprint c ? (x = 1) : 0;\nprint x - 1;").is_empty());
+    }
+
+    #[test]
+    fn blocks_scope_variables() {
+        assert_eq!(errors("var x: Int = 1;\n{\n  var x: String = \"a\";\n  x = 2;\n}\nx = 3;"), [
+            "4 at '=': Can't assign Int to 'x' of type String."
+        ]);
+        assert!(errors("var x = 1;\n{ var x = \"a\"; print x + \"b\"; }\nprint x - 1;").is_empty());
+    }
+
+    #[test]
+    fn unannotated_code_passes() {
+        let source = "var a = [1, 2];\nvar m = {\"k\": a};\nm[\"k\"][0] += 1;\nvar s = \"x\" + str(1);\nprint len(a) > 1 ? s : a;";
+        assert!(errors(source).is_empty());
+    }
+}
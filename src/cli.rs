@@ -0,0 +1,289 @@
+use crate::format::FormatConfig;
+
+pub const USAGE: &str = "\
+Usage: rlox [options] [script | -e <code> | -] [args...]
+       rlox fmt [--check] [--indent <n>] [--width <n>] <file | ->...
+       rlox lint [--config <file>] [--rules] <file | ->...
+       rlox check <file | ->...
+       rlox parse [--emit=json|sexpr|dot] <file | ->
+       rlox run-ast <file | ->
+       rlox lsp
+
+With no script, rlox starts an interactive prompt. Arguments after the
+script are available to it as the `args` list. `rlox check` reports type errors
+in code with type annotations like `var x: Number = 1;`. `rlox parse`
+prints the syntax tree, which `rlox run-ast` reads back and runs; `--emit=dot`
+draws it for Graphviz instead, along with its evaluation order. `rlox lsp` runs a
+language server on standard input and output.
+
+Options:
+  -e, --eval <code>   Run <code> instead of a script file
+  -                   Read the script from standard input
+      --no-history    Don't load or save REPL history
+  -h, --help          Show this help and exit
+  -V, --version       Show the version and exit
+
+Formatter options:
+      --check         Don't write files; fail if any isn't formatted
+      --indent <n>    Spaces per indentation level (default 4)
+      --width <n>     Preferred maximum line width (default 80)
+
+Lint options:
+      --config <file> Read rule settings from <file> (default ./.rloxlint)
+      --rules         List the lint rules and exit
+";
+
+pub enum Command {
+    Repl { save_history: bool },
+    Run { source: Source, args: Vec<String> },
+    Fmt { files: Vec<String>, check: bool, config: FormatConfig },
+    Lint { files: Vec<String>, config: Option<String> },
+    LintRules,
+    Check { files: Vec<String> },
+    Parse { file: String, emit: Emit },
+    RunAst { file: String },
+    Lsp,
+    Help,
+    Version
+}
+
+/// The output format of `rlox parse`.
+pub enum Emit {
+    Json,
+    Sexpr,
+    Dot
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Source {
+    File(String),
+    Inline(String),
+    Stdin
+}
+
+/// Parses the command line, not including the program name. Options must
+/// come before the script; everything after it is passed to the script.
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    match args.first().map(String::as_str) {
+        Some("fmt") => return parse_fmt_args(&args[1..]),
+        Some("lint") => return parse_lint_args(&args[1..]),
+        Some("check") => return parse_check_args(&args[1..]),
+        Some("parse") => return parse_parse_args(&args[1..]),
+        Some("run-ast") => return match &args[1..] {
+            [file] => Ok(Command::RunAst { file: file.clone() }),
+            _ => Err("'run-ast' needs exactly one file.".to_string())
+        },
+        Some("lsp") => return match args.get(1) {
+            Some(arg) => Err(format!("Unexpected argument '{}' to 'lsp'.", arg)),
+            None => Ok(Command::Lsp)
+        },
+        _ => ()
+    }
+
+    let mut save_history = true;
+    let mut source = None;
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--no-history" => save_history = false,
+            "-e" | "--eval" => match rest.next() {
+                Some(code) => {
+                    source = Some(Source::Inline(code.clone()));
+                    break;
+                },
+                None => return Err(format!("'{}' needs an argument.", arg))
+            },
+            "-" => {
+                source = Some(Source::Stdin);
+                break;
+            },
+            "--" => {
+                source = rest.next().map(|script| Source::File(script.clone()));
+                break;
+            },
+            _ if arg.starts_with('-') => return Err(format!("Unknown option '{}'.", arg)),
+            _ => {
+                source = Some(Source::File(arg.clone()));
+                break;
+            }
+        }
+    }
+
+    let script_args: Vec<String> = rest.cloned().collect();
+    match source {
+        Some(source) => Ok(Command::Run { source, args: script_args }),
+        None => Ok(Command::Repl { save_history })
+    }
+}
+
+fn parse_fmt_args(args: &[String]) -> Result<Command, String> {
+    let mut files = Vec::new();
+    let mut check = false;
+    let mut config = FormatConfig::default();
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--indent" => config.indent = number_arg(arg, rest.next())?,
+            "--width" => config.width = number_arg(arg, rest.next())?,
+            "-h" | "--help" => return Ok(Command::Help),
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("Unknown option '{}'.", arg)),
+            _ => files.push(arg.clone())
+        }
+    }
+
+    if files.is_empty() {
+        return Err("'fmt' needs at least one file.".to_string());
+    }
+    Ok(Command::Fmt { files, check, config })
+}
+
+fn parse_lint_args(args: &[String]) -> Result<Command, String> {
+    let mut files = Vec::new();
+    let mut config = None;
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--config" => match rest.next() {
+                Some(file) => config = Some(file.clone()),
+                None => return Err(format!("'{}' needs an argument.", arg))
+            },
+            "--rules" => return Ok(Command::LintRules),
+            "-h" | "--help" => return Ok(Command::Help),
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("Unknown option '{}'.", arg)),
+            _ => files.push(arg.clone())
+        }
+    }
+
+    if files.is_empty() {
+        return Err("'lint' needs at least one file.".to_string());
+    }
+    Ok(Command::Lint { files, config })
+}
+
+fn parse_check_args(args: &[String]) -> Result<Command, String> {
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("Unknown option '{}'.", arg)),
+            _ => files.push(arg.clone())
+        }
+    }
+
+    if files.is_empty() {
+        return Err("'check' needs at least one file.".to_string());
+    }
+    Ok(Command::Check { files })
+}
+
+fn parse_parse_args(args: &[String]) -> Result<Command, String> {
+    let mut file = None;
+    let mut emit = Emit::Json;
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        let format = match arg.as_str() {
+            "--emit" => rest.next().map(String::as_str),
+            _ => arg.strip_prefix("--emit=")
+        };
+        match (arg.as_str(), format) {
+            (_, Some("json")) => emit = Emit::Json,
+            (_, Some("sexpr")) => emit = Emit::Sexpr,
+            (_, Some("dot")) => emit = Emit::Dot,
+            (_, Some(format)) => return Err(format!("Unknown format '{}' for --emit.", format)),
+            ("--emit", None) => return Err("'--emit' needs an argument.".to_string()),
+            ("-h", _) | ("--help", _) => return Ok(Command::Help),
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("Unknown option '{}'.", arg)),
+            _ if file.is_some() => return Err("'parse' takes a single file.".to_string()),
+            _ => file = Some(arg.clone())
+        }
+    }
+
+    match file {
+        Some(file) => Ok(Command::Parse { file, emit }),
+        None => Err("'parse' needs a file.".to_string())
+    }
+}
+
+fn number_arg(option: &str, value: Option<&String>) -> Result<usize, String> {
+    value.and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("'{}' needs a number.", option))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Command, String> {
+        parse_args(&line.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    /// The script and arguments of a command line that runs one.
+    fn run(line: &str) -> (Source, Vec<String>) {
+        match parse(line) {
+            Ok(Command::Run { source, args }) => (source, args),
+            Ok(_) => panic!("'{}' doesn't run a script", line),
+            Err(message) => panic!("'{}' failed: {}", line, message)
+        }
+    }
+
+    fn error(line: &str) -> String {
+        match parse(line) {
+            Err(message) => message,
+            Ok(_) => panic!("'{}' should fail", line)
+        }
+    }
+
+    #[test]
+    fn arguments_after_the_script_go_to_it() {
+        assert_eq!(run("a.lox -e --help -"), (Source::File("a.lox".to_string()), vec!["-e".into(), "--help".into(), "-".into()]));
+        assert_eq!(run("--no-history - x"), (Source::Stdin, vec!["x".into()]));
+        assert!(matches!(parse("--no-history"), Ok(Command::Repl { save_history: false })));
+        assert!(matches!(parse("--help a.lox"), Ok(Command::Help)));
+        assert_eq!(error("--bogus a.lox"), "Unknown option '--bogus'.");
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        assert_eq!(run("-- -e.lox --help"), (Source::File("-e.lox".to_string()), vec!["--help".into()]));
+        assert_eq!(run("--no-history -- a.lox -- b"), (Source::File("a.lox".to_string()), vec!["--".into(), "b".into()]));
+        assert!(matches!(parse("--"), Ok(Command::Repl { save_history: true })));
+    }
+
+    #[test]
+    fn eval_takes_code_and_script_arguments() {
+        assert_eq!(run("-e print(args); a -b"), (Source::Inline("print(args);".to_string()), vec!["a".into(), "-b".into()]));
+        assert_eq!(run("--eval 1 --"), (Source::Inline("1".to_string()), vec!["--".into()]));
+        assert_eq!(run("-e a.lox"), (Source::Inline("a.lox".to_string()), vec![]));
+        assert_eq!(error("-e"), "'-e' needs an argument.");
+    }
+
+    #[test]
+    fn scripts_named_like_subcommands_need_a_path_or_double_dash() {
+        assert_eq!(error("fmt"), "'fmt' needs at least one file.");
+        assert!(matches!(parse("lint fmt"), Ok(Command::Lint { files, .. }) if files == ["fmt"]));
+        assert_eq!(run("-- fmt x"), (Source::File("fmt".to_string()), vec!["x".into()]));
+        assert_eq!(run("./check x"), (Source::File("./check".to_string()), vec!["x".into()]));
+        assert_eq!(run("a.lox fmt"), (Source::File("a.lox".to_string()), vec!["fmt".into()]));
+        // Subcommands are only recognized first, before any option.
+        assert_eq!(run("--no-history lsp"), (Source::File("lsp".to_string()), vec![]));
+    }
+
+    #[test]
+    fn subcommand_arguments() {
+        assert!(matches!(parse("fmt --check --indent 2 a b"), Ok(Command::Fmt { files, check: true, config })
+                         if files == ["a", "b"] && config.indent == 2));
+        assert_eq!(error("fmt --width x a"), "'--width' needs a number.");
+        assert!(matches!(parse("parse --emit dot -"), Ok(Command::Parse { file, emit: Emit::Dot }) if file == "-"));
+        assert_eq!(error("parse --emit=xml a"), "Unknown format 'xml' for --emit.");
+        assert_eq!(error("parse a b"), "'parse' takes a single file.");
+        assert_eq!(error("run-ast"), "'run-ast' needs exactly one file.");
+        assert_eq!(error("lsp x"), "Unexpected argument 'x' to 'lsp'.");
+    }
+}
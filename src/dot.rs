@@ -0,0 +1,433 @@
+//! `rlox parse --emit=dot`: Graphviz output for a program. The `ast` cluster
+//! draws the syntax tree with operands in source order, and the `flow`
+//! cluster draws the order the interpreter evaluates each node in, so a
+//! precedence bug shows up as an operator applied at the wrong step.
+
+use crate::grammar::{Expr, Stmt};
+use crate::token::Literal;
+
+pub fn to_dot(statements: &[Stmt]) -> String {
+    let mut tree = Graph::new("t");
+    let root = tree.node("Program");
+    for stmt in statements {
+        let child = tree_stmt(&mut tree, stmt);
+        tree.edge(&root, &child, "");
+    }
+
+    let mut flow = Graph::new("f");
+    let start = flow.node("start");
+    let mut exit = start;
+    for stmt in statements {
+        let (entry, stmt_exit) = flow_stmt(&mut flow, stmt);
+        flow.edge(&exit, &entry, "");
+        exit = stmt_exit;
+    }
+    let end = flow.node("end");
+    flow.edge(&exit, &end, "");
+
+    let mut dot = String::from("digraph rlox {\n    ordering=out;\n    node [shape=box, fontname=\"monospace\"];\n");
+    tree.write_cluster(&mut dot, "ast", "Syntax tree");
+    flow.write_cluster(&mut dot, "flow", "Evaluation order");
+    dot.push_str("}\n");
+    dot
+}
+
+/// Nodes and edges of one cluster. Node ids share a prefix per cluster so
+/// the two never collide.
+struct Graph {
+    prefix: &'static str,
+    nodes: Vec<String>,
+    edges: Vec<String>
+}
+
+impl Graph {
+    fn new(prefix: &'static str) -> Graph {
+        Graph { prefix, nodes: Vec::new(), edges: Vec::new() }
+    }
+
+    fn node(&mut self, label: &str) -> String {
+        let id = format!("{}{}", self.prefix, self.nodes.len());
+        self.nodes.push(format!("{} [label=\"{}\"];", id, escape(label)));
+        id
+    }
+
+    fn edge(&mut self, from: &str, to: &str, label: &str) {
+        if label.is_empty() {
+            self.edges.push(format!("{} -> {};", from, to));
+        } else {
+            self.edges.push(format!("{} -> {} [label=\"{}\"];", from, to, escape(label)));
+        }
+    }
+
+    fn write_cluster(&self, dot: &mut String, name: &str, label: &str) {
+        dot.push_str(&format!("    subgraph cluster_{} {{\n        label=\"{}\";\n", name, label));
+        for line in self.nodes.iter().chain(&self.edges) {
+            dot.push_str(&format!("        {}\n", line));
+        }
+        dot.push_str("    }\n");
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn literal_label(literal: &Literal) -> String {
+    match literal {
+        Literal::String(string) => format!("\"{}\"", string),
+        _ => literal.to_string()
+    }
+}
+
+fn tree_stmt(tree: &mut Graph, stmt: &Stmt) -> String {
+    match stmt {
+        Stmt::Block(statements) => {
+            let node = tree.node("block");
+            for stmt in statements {
+                let child = tree_stmt(tree, stmt);
+                tree.edge(&node, &child, "");
+            }
+            node
+        },
+        Stmt::Echo(expr) => tree_expr(tree, expr),
+        Stmt::Expression(expr) => {
+            let node = tree.node(";");
+            let child = tree_expr(tree, expr);
+            tree.edge(&node, &child, "");
+            node
+        },
+        Stmt::Print(expr) => {
+            let node = tree.node("print");
+            let child = tree_expr(tree, expr);
+            tree.edge(&node, &child, "");
+            node
+        },
+        Stmt::Var { name, annotation, initializer } => {
+            let label = match annotation {
+                Some(annotation) => format!("var {}: {}", name.details().lexeme, annotation.details().lexeme),
+                None => format!("var {}", name.details().lexeme)
+            };
+            let node = tree.node(&label);
+            if let Some(expr) = initializer {
+                let child = tree_expr(tree, expr);
+                tree.edge(&node, &child, "");
+            }
+            node
+        }
+    }
+}
+
+fn tree_expr(tree: &mut Graph, expr: &Expr) -> String {
+    match expr {
+        Expr::Assign { name, operator, value } => {
+            let node = tree.node(&format!("{} {}", name.details().lexeme, operator.details().lexeme));
+            let child = tree_expr(tree, value);
+            tree.edge(&node, &child, "");
+            node
+        },
+        Expr::Binary { left, operator, right } => {
+            let node = tree.node(&operator.details().lexeme);
+            let left = tree_expr(tree, left);
+            tree.edge(&node, &left, "left");
+            let right = tree_expr(tree, right);
+            tree.edge(&node, &right, "right");
+            node
+        },
+        Expr::Call { callee, arguments, .. } => {
+            let node = tree.node("call");
+            let callee = tree_expr(tree, callee);
+            tree.edge(&node, &callee, "callee");
+            for (i, argument) in arguments.iter().enumerate() {
+                let argument = tree_expr(tree, argument);
+                tree.edge(&node, &argument, &format!("argument {}", i + 1));
+            }
+            node
+        },
+        Expr::Conditional { condition, then_branch, else_branch, .. } => {
+            let node = tree.node("?:");
+            for (label, child) in [("condition", condition), ("then", then_branch), ("else", else_branch)] {
+                let child = tree_expr(tree, child);
+                tree.edge(&node, &child, label);
+            }
+            node
+        },
+        Expr::Get { object, name } => {
+            let node = tree.node(&format!(".{}", name.details().lexeme));
+            let child = tree_expr(tree, object);
+            tree.edge(&node, &child, "");
+            node
+        },
+        Expr::Grouping(inner) => {
+            let node = tree.node("( )");
+            let child = tree_expr(tree, inner);
+            tree.edge(&node, &child, "");
+            node
+        },
+        Expr::Index { object, index, .. } => {
+            let node = tree.node("[ ]");
+            let object = tree_expr(tree, object);
+            tree.edge(&node, &object, "object");
+            let index = tree_expr(tree, index);
+            tree.edge(&node, &index, "index");
+            node
+        },
+        Expr::List(elements) => {
+            let node = tree.node("list");
+            for element in elements {
+                let element = tree_expr(tree, element);
+                tree.edge(&node, &element, "");
+            }
+            node
+        },
+        Expr::Literal(literal) => tree.node(&literal_label(literal)),
+        Expr::Map { entries, .. } => {
+            let node = tree.node("map");
+            for (key, value) in entries {
+                let key = tree_expr(tree, key);
+                tree.edge(&node, &key, "key");
+                let value = tree_expr(tree, value);
+                tree.edge(&node, &value, "value");
+            }
+            node
+        },
+        Expr::SetIndex { object, index, operator, value, .. } => {
+            let node = tree.node(&format!("[ ] {}", operator.details().lexeme));
+            for (label, child) in [("object", object), ("index", index), ("value", value)] {
+                let child = tree_expr(tree, child);
+                tree.edge(&node, &child, label);
+            }
+            node
+        },
+        Expr::Unary { operator, right } => {
+            let node = tree.node(&operator.details().lexeme);
+            let child = tree_expr(tree, right);
+            tree.edge(&node, &child, "");
+            node
+        },
+        Expr::Variable(name) => tree.node(&name.details().lexeme)
+    }
+}
+
+/// Adds the steps of a statement to the flow graph and returns its first
+/// and last step.
+fn flow_stmt(flow: &mut Graph, stmt: &Stmt) -> (String, String) {
+    match stmt {
+        Stmt::Block(statements) => {
+            let entry = flow.node("enter block");
+            let mut exit = entry.clone();
+            for stmt in statements {
+                let (stmt_entry, stmt_exit) = flow_stmt(flow, stmt);
+                flow.edge(&exit, &stmt_entry, "");
+                exit = stmt_exit;
+            }
+            let end = flow.node("leave block");
+            flow.edge(&exit, &end, "");
+            (entry, end)
+        },
+        Stmt::Echo(expr) => flow_expr(flow, expr),
+        Stmt::Expression(expr) => {
+            let (entry, exit) = flow_expr(flow, expr);
+            let node = flow.node("discard");
+            flow.edge(&exit, &node, "");
+            (entry, node)
+        },
+        Stmt::Print(expr) => {
+            let (entry, exit) = flow_expr(flow, expr);
+            let node = flow.node("print");
+            flow.edge(&exit, &node, "");
+            (entry, node)
+        },
+        Stmt::Var { name, initializer, .. } => {
+            let label = format!("define {}", name.details().lexeme);
+            match initializer {
+                Some(expr) => {
+                    let (entry, exit) = flow_expr(flow, expr);
+                    let node = flow.node(&label);
+                    flow.edge(&exit, &node, "");
+                    (entry, node)
+                },
+                None => {
+                    let node = flow.node(&label);
+                    (node.clone(), node)
+                }
+            }
+        }
+    }
+}
+
+/// Adds the steps of an expression in the order `Evaluable::evaluate` takes
+/// them: operands before their operator, a binary operator's left operand
+/// before its right, and only one branch of a conditional.
+fn flow_expr(flow: &mut Graph, expr: &Expr) -> (String, String) {
+    match expr {
+        Expr::Assign { name, operator, value } => {
+            let (entry, exit) = flow_expr(flow, value);
+            let node = flow.node(&format!("{} {}", name.details().lexeme, operator.details().lexeme));
+            flow.edge(&exit, &node, "");
+            (entry, node)
+        },
+        Expr::Binary { left, operator, right } => flow_operands(flow, &[left, right], &operator.details().lexeme),
+        Expr::Call { callee, arguments, .. } => {
+            let operands: Vec<&Expr> = std::iter::once(callee.as_ref()).chain(arguments).collect();
+            flow_operands(flow, &operands, "call")
+        },
+        Expr::Conditional { condition, then_branch, else_branch, .. } => {
+            let (entry, condition_exit) = flow_expr(flow, condition);
+            let test = flow.node("?");
+            flow.edge(&condition_exit, &test, "");
+            let (then_entry, then_exit) = flow_expr(flow, then_branch);
+            let (else_entry, else_exit) = flow_expr(flow, else_branch);
+            flow.edge(&test, &then_entry, "truthy");
+            flow.edge(&test, &else_entry, "falsey");
+            let node = flow.node(":");
+            flow.edge(&then_exit, &node, "");
+            flow.edge(&else_exit, &node, "");
+            (entry, node)
+        },
+        Expr::Get { object, name } => flow_operands(flow, &[object], &format!(".{}", name.details().lexeme)),
+        // A grouping only changes how the tree is built; it adds no step.
+        Expr::Grouping(inner) => flow_expr(flow, inner),
+        Expr::Index { object, index, .. } => flow_operands(flow, &[object, index], "[ ]"),
+        Expr::List(elements) => flow_operands(flow, &elements.iter().collect::<Vec<_>>(), "list"),
+        Expr::Literal(literal) => {
+            let node = flow.node(&literal_label(literal));
+            (node.clone(), node)
+        },
+        Expr::Map { entries, .. } => {
+            let operands: Vec<&Expr> = entries.iter().flat_map(|(key, value)| [key, value]).collect();
+            flow_operands(flow, &operands, "map")
+        },
+        Expr::SetIndex { object, index, operator, value, .. } => {
+            flow_operands(flow, &[object, index, value], &format!("[ ] {}", operator.details().lexeme))
+        },
+        Expr::Unary { operator, right } => {
+            let (entry, exit) = flow_expr(flow, right);
+            let node = flow.node(&operator.details().lexeme);
+            flow.edge(&exit, &node, "");
+            (entry, node)
+        },
+        Expr::Variable(name) => {
+            let node = flow.node(&name.details().lexeme);
+            (node.clone(), node)
+        }
+    }
+}
+
+/// Evaluates `operands` left to right, then the step `label` that uses them.
+fn flow_operands(flow: &mut Graph, operands: &[&Expr], label: &str) -> (String, String) {
+    let mut steps: Option<(String, String)> = None;
+    for operand in operands {
+        let (entry, exit) = flow_expr(flow, operand);
+        steps = match steps {
+            Some((first, last)) => {
+                flow.edge(&last, &entry, "");
+                Some((first, exit))
+            },
+            None => Some((entry, exit))
+        };
+    }
+
+    let node = flow.node(label);
+    match steps {
+        Some((entry, exit)) => {
+            flow.edge(&exit, &node, "");
+            (entry, node)
+        },
+        None => (node.clone(), node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dot(source: &str) -> String {
+        match crate::parse(source.to_string()) {
+            Ok(statements) => to_dot(&statements),
+            Err(errs) => panic!("'{}' didn't parse: {}", source, errs[0].message)
+        }
+    }
+
+    /// The lines of one cluster's body, without its label.
+    fn cluster<'a>(dot: &'a str, name: &str) -> Vec<&'a str> {
+        dot.lines()
+            .skip_while(|line| line.trim() != format!("subgraph cluster_{} {{", name))
+            .skip(2)
+            .take_while(|line| line.trim() != "}")
+            .map(str::trim)
+            .collect()
+    }
+
+    #[test]
+    fn precedence_shows_in_the_evaluation_order() {
+        assert_eq!(dot("1 + 2 * 3;"), r#"digraph rlox {
+    ordering=out;
+    node [shape=box, fontname="monospace"];
+    subgraph cluster_ast {
+        label="Syntax tree";
+        t0 [label="Program"];
+        t1 [label=";"];
+        t2 [label="+"];
+        t3 [label="1"];
+        t4 [label="*"];
+        t5 [label="2"];
+        t6 [label="3"];
+        t2 -> t3 [label="left"];
+        t4 -> t5 [label="left"];
+        t4 -> t6 [label="right"];
+        t2 -> t4 [label="right"];
+        t1 -> t2;
+        t0 -> t1;
+    }
+    subgraph cluster_flow {
+        label="Evaluation order";
+        f0 [label="start"];
+        f1 [label="1"];
+        f2 [label="2"];
+        f3 [label="3"];
+        f4 [label="*"];
+        f5 [label="+"];
+        f6 [label="discard"];
+        f7 [label="end"];
+        f2 -> f3;
+        f3 -> f4;
+        f1 -> f2;
+        f4 -> f5;
+        f5 -> f6;
+        f0 -> f1;
+        f6 -> f7;
+    }
+}
+"#);
+    }
+
+    #[test]
+    fn operands_are_drawn_left_and_right() {
+        let graph = dot("a - b;");
+        let tree = cluster(&graph, "ast");
+        assert!(tree.contains(&r#"t2 [label="-"];"#));
+        assert!(tree.contains(&r#"t3 [label="a"];"#));
+        assert!(tree.contains(&r#"t4 [label="b"];"#));
+        assert!(tree.contains(&r#"t2 -> t3 [label="left"];"#));
+        assert!(tree.contains(&r#"t2 -> t4 [label="right"];"#));
+
+        // Grouping on the right puts the inner operator under the right edge.
+        let graph = dot("a - (b - c);");
+        let tree = cluster(&graph, "ast");
+        assert!(tree.contains(&r#"t2 -> t3 [label="left"];"#));
+        assert!(tree.contains(&r#"t4 [label="( )"];"#));
+        assert!(tree.contains(&r#"t2 -> t4 [label="right"];"#));
+        assert!(tree.contains(&r#"t5 [label="-"];"#));
+    }
+
+    #[test]
+    fn conditionals_take_one_branch() {
+        let graph = dot("a ? b : c;");
+        let flow = cluster(&graph, "flow");
+        assert!(flow.contains(&r#"f2 [label="?"];"#));
+        assert!(flow.contains(&r#"f2 -> f3 [label="truthy"];"#));
+        assert!(flow.contains(&r#"f2 -> f4 [label="falsey"];"#));
+        assert!(flow.contains(&"f3 -> f5;") && flow.contains(&"f4 -> f5;"));
+        assert!(cluster(&graph, "ast").contains(&r#"t2 -> t3 [label="condition"];"#));
+    }
+}
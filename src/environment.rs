@@ -0,0 +1,68 @@
+use crate::eval::Value;
+use crate::token::Token;
+use crate::RloxError;
+use std::collections::HashMap;
+
+/// Variable storage as a stack of scopes. The bottom scope holds globals and
+/// each block pushes a new scope on top of it.
+pub struct Environment {
+    scopes: Vec<HashMap<String, Value>>
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment {
+            scopes: vec![HashMap::new()]
+        }
+    }
+}
+
+impl Environment {
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.scopes.last_mut()
+            .expect("Environment has no scope.")
+            .insert(name.to_string(), value);
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.scopes[0].insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value, RloxError> {
+        let lexeme = name.details().lexeme;
+        self.scopes.iter().rev()
+            .find_map(|scope| scope.get(&lexeme))
+            .cloned()
+            .ok_or_else(|| undefined_err(name))
+    }
+
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<(), RloxError> {
+        let lexeme = name.details().lexeme;
+        match self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(&lexeme)) {
+            Some(slot) => {
+                *slot = value;
+                Ok(())
+            },
+            None => Err(undefined_err(name))
+        }
+    }
+
+    pub fn global_names(&self) -> Vec<String> {
+        self.scopes[0].keys().cloned().collect()
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+}
+
+fn undefined_err(name: &Token) -> RloxError {
+    let details = name.details();
+    RloxError::new(details.line, &format!("Undefined variable '{}'.", details.lexeme), "")
+}
@@ -1,112 +1,476 @@
-use crate::grammar::Expr;
+use crate::environment::Environment;
+use crate::grammar::{Expr, Stmt};
+use crate::map::Map;
+use crate::natives::{Module, NativeFunction};
+use crate::scanner;
 use crate::token::{Literal, TokenType, Token};
 use crate::RloxError;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
 use std::fmt;
+use std::rc::Rc;
 
 pub trait Evaluable {
-    fn evaluate(&self) -> Result<Value, RloxError>;
+    fn evaluate(&self, env: &mut Environment) -> Result<Value, RloxError>;
+}
+
+pub trait Executable {
+    /// Runs the statement. Only `Stmt::Echo` produces a value.
+    fn execute(&self, env: &mut Environment) -> Result<Option<Value>, RloxError>;
 }
 
 impl Evaluable for Expr {
-    fn evaluate(&self) -> Result<Value, RloxError> {
+    fn evaluate(&self, env: &mut Environment) -> Result<Value, RloxError> {
         match self {
-            Expr::Binary { left, operator, right } => eval_binary(left, operator, right),
-            Expr::Grouping(expr) => expr.evaluate(),
+            Expr::Assign { name, operator, value } => eval_assign(env, name, operator, value),
+            Expr::Binary { left, operator, right } => eval_binary(env, left, operator, right),
+            Expr::Call { callee, paren, arguments } => eval_call(env, callee, paren, arguments),
+            Expr::Conditional { condition, then_branch, else_branch, .. } => {
+                if condition.evaluate(env)?.is_truthy() {
+                    then_branch.evaluate(env)
+                } else {
+                    else_branch.evaluate(env)
+                }
+            },
+            Expr::Get { object, name } => match object.evaluate(env)? {
+                Value::Module(module) => module.get(&name.details().lexeme).ok_or_else(|| {
+                    runtime_err(name, &format!("Module '{}' has no member '{}'.", module.name, name.details().lexeme))
+                }),
+                object => Err(runtime_err(name, &format!("Only modules have members, not {}.", object.type_name())))
+            },
+            Expr::Grouping(expr) => expr.evaluate(env),
+            Expr::Index { object, bracket, index } => {
+                let object = object.evaluate(env)?;
+                let index = index.evaluate(env)?;
+                get_index(bracket, &object, &index)
+            },
+            Expr::List(elements) => evaluate_all(env, elements).map(Value::list),
+            Expr::Map { brace, entries } => {
+                let mut map = Map::default();
+                for (key, value) in entries {
+                    let key = key.evaluate(env)?;
+                    let value = value.evaluate(env)?;
+                    map.insert(key, value).map_err(|message| runtime_err(brace, &message))?;
+                }
+                Ok(Value::Map(Rc::new(RefCell::new(map))))
+            },
             Expr::Literal(literal) => Ok(Value::from(literal)),
-            Expr::Unary { operator, right } => eval_unary(operator, right)
+            Expr::SetIndex { object, bracket, index, operator, value } => {
+                eval_set_index(env, object, bracket, index, operator, value)
+            },
+            Expr::Unary { operator, right } => eval_unary(env, operator, right),
+            Expr::Variable(name) => env.get(name)
+        }
+    }
+}
+
+impl Executable for Stmt {
+    fn execute(&self, env: &mut Environment) -> Result<Option<Value>, RloxError> {
+        match self {
+            Stmt::Block(statements) => {
+                env.push_scope();
+                let result = statements.iter().try_for_each(|stmt| stmt.execute(env).map(|_| ()));
+                env.pop_scope();
+                result.map(|_| None)
+            },
+            Stmt::Echo(expr) => expr.evaluate(env).map(Some),
+            Stmt::Expression(expr) => expr.evaluate(env).map(|_| None),
+            Stmt::Print(expr) => expr.evaluate(env).map(|value| {
+                println!("{}", value);
+                None
+            }),
+            Stmt::Var { name, initializer, .. } => {
+                let value = match initializer {
+                    Some(expr) => expr.evaluate(env)?,
+                    None => Value::Nil
+                };
+                env.define(&name.details().lexeme, value);
+                Ok(None)
+            }
         }
     }
 }
 
-fn eval_unary(operator: &Token, right: &Box<Expr>) -> Result<Value, RloxError> {
-    right.evaluate().and_then(|right| {
+fn eval_assign(env: &mut Environment, name: &Token, operator: &Token, value: &Expr) -> Result<Value, RloxError> {
+    let value = value.evaluate(env)?;
+    let value = match compound_operator(operator) {
+        Some(token_type) => apply_binary(operator, token_type, env.get(name)?, value)?,
+        None => value
+    };
+
+    env.assign(name, value.clone()).map(|_| value)
+}
+
+/// The binary operator a compound assignment like `+=` applies, if it is one.
+fn compound_operator(operator: &Token) -> Option<TokenType> {
+    match operator.details().token_type {
+        TokenType::PlusEqual => Some(TokenType::Plus),
+        TokenType::MinusEqual => Some(TokenType::Minus),
+        TokenType::StarEqual => Some(TokenType::Star),
+        TokenType::SlashEqual => Some(TokenType::Slash),
+        _ => None
+    }
+}
+
+fn evaluate_all(env: &mut Environment, exprs: &[Expr]) -> Result<Vec<Value>, RloxError> {
+    exprs.iter().map(|expr| expr.evaluate(env)).collect()
+}
+
+fn eval_call(env: &mut Environment, callee: &Expr, paren: &Token, arguments: &[Expr]) -> Result<Value, RloxError> {
+    let callee = callee.evaluate(env)?;
+    let arguments = evaluate_all(env, arguments)?;
+    match callee {
+        Value::Native(function) => function.call(paren, arguments),
+        _ => Err(runtime_err(paren, &format!("Can only call functions, not {}.", callee.type_name())))
+    }
+}
+
+fn get_index(bracket: &Token, object: &Value, index: &Value) -> Result<Value, RloxError> {
+    match object {
+        Value::List(values) => {
+            let values = values.borrow();
+            sequence_index(bracket, "List", index, values.len()).map(|i| values[i].clone())
+        },
+        // Strings index by Unicode scalar, not by byte.
+        Value::String(string) => {
+            let i = sequence_index(bracket, "String", index, string.chars().count())?;
+            Ok(Value::String(string.chars().nth(i).map(String::from).unwrap_or_default()))
+        },
+        Value::Map(map) => match map.borrow().get(index) {
+            Ok(Some(value)) => Ok(value.clone()),
+            Ok(None) => Err(runtime_err(bracket, &format!("Key {} isn't in the map.", index.repr()))),
+            Err(message) => Err(runtime_err(bracket, &message))
+        },
+        _ => Err(runtime_err(bracket, &format!("Can't index {}.", object.type_name())))
+    }
+}
+
+/// Evaluates the object, the index and then the value, and stores the
+/// value. A compound assignment reads the old element first.
+fn eval_set_index(
+    env: &mut Environment,
+    object: &Expr,
+    bracket: &Token,
+    index: &Expr,
+    operator: &Token,
+    value: &Expr
+) -> Result<Value, RloxError> {
+    let object = object.evaluate(env)?;
+    let index = index.evaluate(env)?;
+    let value = value.evaluate(env)?;
+    let value = match compound_operator(operator) {
+        Some(token_type) => apply_binary(operator, token_type, get_index(bracket, &object, &index)?, value)?,
+        None => value
+    };
+
+    match &object {
+        Value::List(values) => {
+            let mut values = values.borrow_mut();
+            let i = sequence_index(bracket, "List", &index, values.len())?;
+            values[i] = value.clone();
+            Ok(value)
+        },
+        Value::Map(map) => {
+            map.borrow_mut().insert(index, value.clone()).map_err(|message| runtime_err(bracket, &message))?;
+            Ok(value)
+        },
+        _ => Err(runtime_err(bracket, &format!("Can't assign to an index of {}.", object.type_name())))
+    }
+}
+
+/// Checks that `index` is an integer in bounds for a `kind` (`List` or
+/// `String`) of `len` elements and returns it as a position.
+pub fn sequence_index(token: &Token, kind: &str, index: &Value, len: usize) -> Result<usize, RloxError> {
+    let index = match index {
+        Value::Int(n) => *n,
+        _ => return Err(runtime_err(token, &format!("{} index must be an integer, not {}.", kind, index.type_name())))
+    };
+    usize::try_from(index).ok()
+        .filter(|i| *i < len)
+        .ok_or_else(|| {
+            let message = format!("Index {} is out of bounds for a {} of length {}.", index, kind.to_lowercase(), len);
+            runtime_err(token, &message)
+        })
+}
+
+fn eval_unary(env: &mut Environment, operator: &Token, right: &Expr) -> Result<Value, RloxError> {
+    right.evaluate(env).and_then(|right| {
         match operator.details().token_type {
             TokenType::Bang => Ok(Value::Boolean(!right.is_truthy())),
-            TokenType::Minus => right.cast_number().and_then(|n| Ok(Value::Number(-n))),
+            TokenType::Minus => match right {
+                Value::Int(n) => n.checked_neg().map(Value::Int).ok_or_else(|| runtime_err(operator, OVERFLOW)),
+                _ => right.as_number()
+                    .map(|n| Value::Number(-n))
+                    .ok_or_else(|| runtime_err(operator, &format!("Operand must be a number, not {}.", right.type_name())))
+            },
+            TokenType::Tilde => match right {
+                Value::Int(n) => Ok(Value::Int(!n)),
+                _ => Err(runtime_err(operator, &format!("Operand must be an integer, not {}.", right.type_name())))
+            },
             _ => Ok(Value::Nil) // unreachable
         }
     })
 }
 
-fn eval_binary(left: &Box<Expr>, operator: &Token, right: &Box<Expr>) -> Result<Value, RloxError> {
-    left.evaluate().and_then(|left| right.evaluate().and_then(|right| {
-        match operator.details().token_type {
-            TokenType::Greater => cast_nums(&left, &right).and_then(|(l, r)| Ok(Value::Boolean(l > r))),
-            TokenType::GreaterEqual => cast_nums(&left, &right).and_then(|(l, r)| Ok(Value::Boolean(l >= r))),
-            TokenType::Less => cast_nums(&left, &right).and_then(|(l, r)| Ok(Value::Boolean(l < r))),
-            TokenType::LessEqual => cast_nums(&left, &right).and_then(|(l, r)| Ok(Value::Boolean(l <= r))),
-            TokenType::Minus => cast_nums(&left, &right).and_then(|(l, r)| Ok(Value::Number(l - r))),
-            TokenType::Slash => cast_nums(&left, &right).and_then(|(l, r)| Ok(Value::Number(l / r))),
-            TokenType::Star => cast_nums(&left, &right).and_then(|(l, r)| Ok(Value::Number(l * r))),
-            TokenType::Plus => cast_nums(&left, &right).and_then(|(l, r)| Ok(Value::Number(l + r)))
-                .or_else(|_| cast_strs(&left, &right).and_then(|(l, r)| Ok(Value::String(format!("{}{}", l, r)))))
-                .or(Err(cast_err("string or number"))),
-            TokenType::EqualEqual => Ok(Value::Boolean(left.is_equal(&right))),
-            TokenType::BangEqual => Ok(Value::Boolean(!left.is_equal(&right))),
-            _ => Ok(Value::Nil) // unreachable
-        }
-    }))
+fn eval_binary(env: &mut Environment, left: &Expr, operator: &Token, right: &Expr) -> Result<Value, RloxError> {
+    let left = left.evaluate(env)?;
+    let right = right.evaluate(env)?;
+    apply_binary(operator, operator.details().token_type, left, right)
+}
+
+/// Applies a binary operator to evaluated operands. `token_type` is passed
+/// separately so compound assignments like `+=` can reuse the `+` rules.
+fn apply_binary(operator: &Token, token_type: TokenType, left: Value, right: Value) -> Result<Value, RloxError> {
+    match token_type {
+        TokenType::Greater => compare(operator, &left, &right, Ordering::is_gt),
+        TokenType::GreaterEqual => compare(operator, &left, &right, Ordering::is_ge),
+        TokenType::Less => compare(operator, &left, &right, Ordering::is_lt),
+        TokenType::LessEqual => compare(operator, &left, &right, Ordering::is_le),
+        TokenType::Minus => arithmetic(operator, &left, &right, |l, r| l.checked_sub(r).ok_or(OVERFLOW), |l, r| l - r),
+        TokenType::Slash => arithmetic(operator, &left, &right, checked_div, |l, r| l / r),
+        TokenType::Percent => arithmetic(operator, &left, &right, checked_rem, |l, r| l % r),
+        TokenType::Star => arithmetic(operator, &left, &right, |l, r| l.checked_mul(r).ok_or(OVERFLOW), |l, r| l * r),
+        TokenType::StarStar => power(operator, &left, &right),
+        TokenType::Plus => match (&left, &right) {
+            (Value::String(l), Value::String(r)) => Ok(Value::String(format!("{}{}", l, r))),
+            _ if left.is_number() && right.is_number() => {
+                arithmetic(operator, &left, &right, |l, r| l.checked_add(r).ok_or(OVERFLOW), |l, r| l + r)
+            },
+            _ => Err(operand_err(operator, "Operands must be two numbers or two strings", &left, &right))
+        },
+        TokenType::Ampersand => bitwise(operator, &left, &right, |l, r| Ok(l & r)),
+        TokenType::Pipe => bitwise(operator, &left, &right, |l, r| Ok(l | r)),
+        TokenType::Caret => bitwise(operator, &left, &right, |l, r| Ok(l ^ r)),
+        TokenType::LessLess => bitwise(operator, &left, &right, |l, r| {
+            u32::try_from(r).ok().and_then(|r| l.checked_shl(r)).ok_or(SHIFT_RANGE)
+        }),
+        TokenType::GreaterGreater => bitwise(operator, &left, &right, |l, r| {
+            u32::try_from(r).ok().and_then(|r| l.checked_shr(r)).ok_or(SHIFT_RANGE)
+        }),
+        TokenType::EqualEqual => Ok(Value::Boolean(left.is_equal(&right))),
+        TokenType::BangEqual => Ok(Value::Boolean(!left.is_equal(&right))),
+        TokenType::Comma => Ok(right),
+        _ => Ok(Value::Nil) // unreachable
+    }
+}
+
+/// Applies an arithmetic operator. Two integers stay integers, anything
+/// else is promoted to a float.
+fn arithmetic(
+    operator: &Token,
+    left: &Value,
+    right: &Value,
+    int_op: fn(i64, i64) -> Result<i64, &'static str>,
+    float_op: fn(f64, f64) -> f64
+) -> Result<Value, RloxError> {
+    match cast_nums(operator, left, right)? {
+        Numbers::Int(l, r) => int_op(l, r).map(Value::Int).map_err(|message| runtime_err(operator, message)),
+        Numbers::Float(l, r) => Ok(Value::Number(float_op(l, r)))
+    }
+}
+
+fn compare(operator: &Token, left: &Value, right: &Value, test: fn(Ordering) -> bool) -> Result<Value, RloxError> {
+    cast_nums(operator, left, right)?;
+    Ok(Value::Boolean(compare_numbers(left, right).is_some_and(test)))
+}
+
+/// Orders two numbers exactly, without rounding an integer to a float, so
+/// `9007199254740993 > 9007199254740992.0`. NaN is unordered.
+fn compare_numbers(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Int(l), Value::Int(r)) => Some(l.cmp(r)),
+        (Value::Number(l), Value::Number(r)) => l.partial_cmp(r),
+        (Value::Int(l), Value::Number(r)) => compare_int_float(*l, *r),
+        (Value::Number(l), Value::Int(r)) => compare_int_float(*r, *l).map(Ordering::reverse),
+        _ => None
+    }
+}
+
+fn compare_int_float(int: i64, float: f64) -> Option<Ordering> {
+    if float.is_nan() {
+        None
+    } else if float >= 9223372036854775808.0 {
+        Some(Ordering::Less)
+    } else if float < -9223372036854775808.0 {
+        Some(Ordering::Greater)
+    } else {
+        // The whole part fits in an integer; the fraction breaks ties.
+        let whole = float.trunc();
+        Some(int.cmp(&(whole as i64)).then_with(|| 0.0.partial_cmp(&(float - whole)).unwrap_or(Ordering::Equal)))
+    }
+}
+
+/// Integer exponents stay integers; a negative exponent gives a float.
+fn power(operator: &Token, left: &Value, right: &Value) -> Result<Value, RloxError> {
+    match cast_nums(operator, left, right)? {
+        Numbers::Int(l, r) if r >= 0 => u32::try_from(r).ok()
+            .and_then(|r| l.checked_pow(r))
+            .map(Value::Int)
+            .ok_or_else(|| runtime_err(operator, OVERFLOW)),
+        Numbers::Int(l, r) => Ok(Value::Number((l as f64).powf(r as f64))),
+        Numbers::Float(l, r) => Ok(Value::Number(l.powf(r)))
+    }
+}
+
+fn bitwise(
+    operator: &Token,
+    left: &Value,
+    right: &Value,
+    op: fn(i64, i64) -> Result<i64, &'static str>
+) -> Result<Value, RloxError> {
+    match (left, right) {
+        (Value::Int(l), Value::Int(r)) => op(*l, *r).map(Value::Int).map_err(|message| runtime_err(operator, message)),
+        _ => Err(operand_err(operator, "Operands must be integers", left, right))
+    }
+}
+
+fn checked_div(left: i64, right: i64) -> Result<i64, &'static str> {
+    if right == 0 {
+        return Err("Division by zero.");
+    }
+    left.checked_div(right).ok_or(OVERFLOW)
+}
+
+fn checked_rem(left: i64, right: i64) -> Result<i64, &'static str> {
+    if right == 0 {
+        return Err("Modulo by zero.");
+    }
+    left.checked_rem(right).ok_or(OVERFLOW)
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     String(String),
+    Int(i64),
     Number(f64),
     Boolean(bool),
+    /// Lists are shared by reference, so every copy sees the same elements.
+    List(Rc<RefCell<Vec<Value>>>),
+    /// Maps are shared by reference like lists.
+    Map(Rc<RefCell<Map>>),
+    Native(&'static NativeFunction),
+    Module(&'static Module),
     Nil
 }
 
 impl Value {
-    fn from(literal: &Literal) -> Value {
+    pub fn from(literal: &Literal) -> Value {
         match literal {
             Literal::String(string) => Value::String(string.clone()),
+            Literal::Int(n) => Value::Int(*n),
             Literal::Number(n) => Value::Number(*n),
             Literal::Nil => Value::Nil,
             Literal::True => Value::Boolean(true),
             Literal::False => Value::Boolean(false)
         }
     }
+
+    pub fn list(values: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(values)))
+    }
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let string = match self {
             Value::String(string) => string.to_owned(),
-            Value::Number(n) => {
-                let num = n;
-                format!("{}", num)
-            },
+            Value::Int(n) => format!("{}", n),
+            Value::Number(n) => format!("{:?}", n),
             Value::Nil => "nil".to_owned(),
-            Value::Boolean(b) => String::from(if *b { "true" } else { "false" })
+            Value::Boolean(b) => String::from(if *b { "true" } else { "false" }),
+            Value::List(_) | Value::Map(_) => self.repr(),
+            Value::Native(function) => format!("<native fn {}>", function.name),
+            Value::Module(module) => format!("<module {}>", module.name)
         };
         write!(f, "{}", string)
     }
 }
 
-fn cast_nums(left: &Value, right: &Value) -> Result<(f64, f64), RloxError> {
-    left.cast_number().and_then(|left| right.cast_number().and_then(|right| Ok((left, right))))
+impl Value {
+    /// The value as it would be written in source, so reading it back gives
+    /// an equal value: strings are quoted and their special characters
+    /// escaped, and natives and modules are written by name. Only a list or
+    /// map that contains itself can't be written back.
+    pub fn repr(&self) -> String {
+        self.repr_inside(&mut Vec::new())
+    }
+
+    /// `repr` for a value inside the lists and maps in `outer`. One that
+    /// contains itself is written `[...]` or `{...}` where it repeats.
+    fn repr_inside(&self, outer: &mut Vec<*const ()>) -> String {
+        match self {
+            Value::List(values) if outer.contains(&(Rc::as_ptr(values) as *const ())) => "[...]".to_string(),
+            Value::List(values) => {
+                outer.push(Rc::as_ptr(values) as *const ());
+                let items: Vec<String> = values.borrow().iter().map(|value| value.repr_inside(outer)).collect();
+                outer.pop();
+                format!("[{}]", items.join(", "))
+            },
+            Value::Map(map) if outer.contains(&(Rc::as_ptr(map) as *const ())) => "{...}".to_string(),
+            Value::Map(map) => {
+                outer.push(Rc::as_ptr(map) as *const ());
+                let entries: Vec<String> = map.borrow().entries().iter()
+                    .map(|(key, value)| format!("{}: {}", key.repr_inside(outer), value.repr_inside(outer)))
+                    .collect();
+                outer.pop();
+                format!("{{{}}}", entries.join(", "))
+            },
+            Value::String(string) => scanner::quote(string),
+            // Literals can't be negative or too big for an integer, so
+            // these are written as the expressions that make them.
+            Value::Int(i64::MIN) => format!("({} - 1)", i64::MIN + 1),
+            Value::Number(n) if n.is_nan() => "math.nan".to_string(),
+            Value::Number(n) if n.is_infinite() => if *n > 0.0 { "math.inf" } else { "-math.inf" }.to_string(),
+            Value::Native(function) => function.name.to_string(),
+            Value::Module(module) => module.name.to_string(),
+            _ => format!("{}", self)
+        }
+    }
+}
+
+enum Numbers {
+    Int(i64, i64),
+    Float(f64, f64)
 }
 
-fn cast_strs(left: &Value, right: &Value) -> Result<(String, String), RloxError> {
-    left.cast_string().and_then(|left| right.cast_string().and_then(|right| Ok((left, right))))
+fn cast_nums(operator: &Token, left: &Value, right: &Value) -> Result<Numbers, RloxError> {
+    match (left, right) {
+        (Value::Int(l), Value::Int(r)) => Ok(Numbers::Int(*l, *r)),
+        _ => left.as_number()
+            .and_then(|l| right.as_number().map(|r| Numbers::Float(l, r)))
+            .ok_or_else(|| operand_err(operator, "Operands must be numbers", left, right))
+    }
 }
 
 impl Value {
-    fn cast_number(&self) -> Result<f64, RloxError> {
+    fn as_number(&self) -> Option<f64> {
         match self {
-            Value::Number(n) => Ok(*n),
-            _ => Err(cast_err("number")),
+            Value::Int(n) => Some(*n as f64),
+            Value::Number(n) => Some(*n),
+            _ => None
         }
     }
 
-    fn cast_string(&self) -> Result<String, RloxError> {
+    pub fn type_name(&self) -> &'static str {
         match self {
-            Value::String(s) => Ok(s.clone()),
-            _ => Err(cast_err("string")),
+            Value::String(_) => "String",
+            Value::Int(_) => "Int",
+            Value::Number(_) => "Number",
+            Value::Boolean(_) => "Bool",
+            Value::List(_) => "List",
+            Value::Map(_) => "Map",
+            Value::Native(_) => "Function",
+            Value::Module(_) => "Module",
+            Value::Nil => "Nil"
         }
     }
 
-    fn is_truthy(&self) -> bool {
+    fn is_number(&self) -> bool {
+        matches!(self, Value::Int(_) | Value::Number(_))
+    }
+
+    pub fn is_truthy(&self) -> bool {
         match self {
             Value::Nil => false,
             Value::Boolean(b) => *b,
@@ -114,19 +478,178 @@ impl Value {
         }
     }
 
-    fn is_equal(&self, other: &Value) -> bool {
+    pub fn is_equal(&self, other: &Value) -> bool {
+        self.is_equal_inside(other, &mut Vec::new())
+    }
+
+    /// `is_equal` for values inside the pairs of lists and maps in
+    /// `compared`. A pair met again is taken to be equal, so values that
+    /// contain themselves compare by their shape instead of recursing forever.
+    fn is_equal_inside(&self, other: &Value, compared: &mut Vec<(*const (), *const ())>) -> bool {
         match self {
-            Value::Nil => {
-                match other {
-                    Value::Nil => true,
-                    _ => false
-                }
+            Value::Nil => matches!(other, Value::Nil),
+            Value::Int(_) | Value::Number(_) => compare_numbers(self, other) == Some(Ordering::Equal),
+            // Elements compare like values, so `[1] == [1.0]`.
+            Value::List(values) => match other {
+                Value::List(others) if Rc::ptr_eq(values, others) => true,
+                Value::List(others) => {
+                    if !compared_first_time(compared, Rc::as_ptr(values) as *const (), Rc::as_ptr(others) as *const ()) {
+                        return true;
+                    }
+                    let (values, others) = (values.borrow(), others.borrow());
+                    values.len() == others.len()
+                        && values.iter().zip(others.iter()).all(|(l, r)| l.is_equal_inside(r, compared))
+                },
+                _ => false
+            },
+            // Same keys, with values that compare equal.
+            Value::Map(map) => match other {
+                Value::Map(others) if Rc::ptr_eq(map, others) => true,
+                Value::Map(others) => {
+                    if !compared_first_time(compared, Rc::as_ptr(map) as *const (), Rc::as_ptr(others) as *const ()) {
+                        return true;
+                    }
+                    let (map, others) = (map.borrow(), others.borrow());
+                    map.entries().len() == others.entries().len() && map.entries().iter().all(|(key, value)| {
+                        matches!(others.get(key), Ok(Some(other)) if value.is_equal_inside(other, compared))
+                    })
+                },
+                _ => false
             },
             _ => self == other
         }
     }
 }
 
-fn cast_err(cast_type: &str) -> RloxError {
-    RloxError::internal(&format!("Couldn't parse {}", cast_type), "")
-}
\ No newline at end of file
+/// Records that the containers at `left` and `right` are being compared,
+/// returning false if they already were.
+fn compared_first_time(compared: &mut Vec<(*const (), *const ())>, left: *const (), right: *const ()) -> bool {
+    if compared.contains(&(left, right)) {
+        return false;
+    }
+    compared.push((left, right));
+    true
+}
+
+/// An operator's error for operands of the wrong types, naming them.
+fn operand_err(operator: &Token, message: &str, left: &Value, right: &Value) -> RloxError {
+    runtime_err(operator, &format!("{}, not {} and {}.", message, left.type_name(), right.type_name()))
+}
+
+const OVERFLOW: &str = "Integer overflow.";
+const SHIFT_RANGE: &str = "Shift amount out of range.";
+
+pub fn runtime_err(operator: &Token, message: &str) -> RloxError {
+    let details = operator.details();
+    RloxError::new(details.line, message, &format!(" at '{}'", details.lexeme))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    /// The value of the trailing expression in `source`.
+    fn eval(source: &str) -> Value {
+        crate::run(source.to_string(), &mut Interpreter::new())
+            .unwrap_or_else(|errs| panic!("{:?}", errs))
+            .expect("Source should end in an expression.")
+    }
+
+    /// The error `source` stops with, as it would be reported.
+    fn error(source: &str) -> String {
+        let errs = crate::run(source.to_string(), &mut Interpreter::new()).expect_err("Source should fail.");
+        format!("[line {}] Error{}: {}", errs[0].line.unwrap_or(0), errs[0].location, errs[0].message)
+    }
+
+    #[test]
+    fn power_is_right_associative_and_binds_tighter_than_unary_minus() {
+        assert_eq!(eval("2 ** 3 ** 2"), Value::Int(512));
+        assert_eq!(eval("-2 ** 2"), Value::Int(-4));
+        assert_eq!(eval("(-2) ** 2"), Value::Int(4));
+        assert_eq!(eval("2 ** -1"), Value::Number(0.5));
+        assert_eq!(eval("2 * 3 ** 2"), Value::Int(18));
+        assert_eq!(error("2 ** 63"), "[line 1] Error at '**': Integer overflow.");
+    }
+
+    #[test]
+    fn shifts_out_of_range_are_an_error() {
+        assert_eq!(eval("1 << 63"), Value::Int(i64::MIN));
+        assert_eq!(eval("-8 >> 1"), Value::Int(-4));
+        assert_eq!(error("1 << 64"), "[line 1] Error at '<<': Shift amount out of range.");
+        assert_eq!(error("1 >> -1"), "[line 1] Error at '>>': Shift amount out of range.");
+    }
+
+    #[test]
+    fn compound_assignment() {
+        assert_eq!(eval("var a = 5;\na += 2;\na -= 1;\na *= 3;\na /= 4;\na"), Value::Int(4));
+        assert_eq!(eval("var s = \"a\";\ns += \"b\";\ns"), Value::String("ab".to_string()));
+        assert_eq!(eval("var l = [1, 2];\nl[1] *= 10;\nl[0] -= 0.5;\nl"), eval("[0.5, 20]"));
+        assert_eq!(eval("var a = 1;\nvar b = a += 1;\n[a, b]"), eval("[2, 2]"));
+        assert_eq!(error("var a = 1;\na /= 0;"), "[line 2] Error at '/=': Division by zero.");
+    }
+
+    #[test]
+    fn operators_report_their_operand_types() {
+        assert_eq!(error("\"a\" % 2"), "[line 1] Error at '%': Operands must be numbers, not String and Int.");
+        assert_eq!(error("\"a\" ** 2"), "[line 1] Error at '**': Operands must be numbers, not String and Int.");
+        assert_eq!(error("var s = \"a\";\ns -= 1;"), "[line 2] Error at '-=': Operands must be numbers, not String and Int.");
+        assert_eq!(error("var l = [nil];\nl[0] *= 2;"), "[line 2] Error at '*=': Operands must be numbers, not Nil and Int.");
+        assert_eq!(error("1 + nil"), "[line 1] Error at '+': Operands must be two numbers or two strings, not Int and Nil.");
+        assert_eq!(error("1 < \"2\""), "[line 1] Error at '<': Operands must be numbers, not Int and String.");
+        assert_eq!(error("1.5 & 1"), "[line 1] Error at '&': Operands must be integers, not Number and Int.");
+        assert_eq!(error("-true"), "[line 1] Error at '-': Operand must be a number, not Bool.");
+        assert_eq!(error("~1.0"), "[line 1] Error at '~': Operand must be an integer, not Number.");
+    }
+
+    #[test]
+    fn integers_and_floats_compare_exactly() {
+        assert_eq!(eval("9007199254740993 == 9007199254740992.0"), Value::Boolean(false));
+        assert_eq!(eval("9007199254740992 == 9007199254740992.0"), Value::Boolean(true));
+        assert_eq!(eval("9007199254740993 > 9007199254740992.0"), Value::Boolean(true));
+        assert_eq!(eval("9223372036854775807 < 9223372036854775808.0"), Value::Boolean(true));
+        assert_eq!(eval("-1 < -0.5"), Value::Boolean(true));
+        assert_eq!(eval("1 == 1.0"), Value::Boolean(true));
+        assert_eq!(eval("1 < 0 / 0.0"), Value::Boolean(false));
+    }
+
+    #[test]
+    fn lists_that_contain_themselves_compare_by_shape() {
+        let setup = "var a = [1]; push(a, a); var b = [1]; push(b, b); var c = [2]; push(c, c);";
+        let compared = eval(&format!("{} [a == b, a != b, a == c, contains([a], b), contains([a], c)]", setup));
+        assert_eq!(compared, eval("[true, false, false, true, false]"));
+        // The cycles have different lengths but the same shape.
+        assert_eq!(eval("var a = [1]; push(a, a); var b = [1, [1]]; push(b[1], b); a == b"), Value::Boolean(true));
+        assert_eq!(eval("var a = [1]; push(a, a); var b = [1, [1]]; push(b[1], 2); a == b"), Value::Boolean(false));
+    }
+
+    #[test]
+    fn maps_that_contain_themselves_compare_by_shape() {
+        let setup = "var a = {\"k\": 1}; a[\"s\"] = a; var b = {\"k\": 1}; b[\"s\"] = b; var c = {\"k\": 2}; c[\"s\"] = c;";
+        let compared = eval(&format!("{} [a == b, a != b, a == c, contains([a], b), contains([a], c)]", setup));
+        assert_eq!(compared, eval("[true, false, false, true, false]"));
+        // A cycle through a list and a map.
+        assert_eq!(eval("var a = {\"l\": []}; push(a[\"l\"], a); var b = {\"l\": []}; push(b[\"l\"], b); a == b"), Value::Boolean(true));
+    }
+
+    #[test]
+    fn power_of_floats_uses_the_platform_pow() {
+        assert_eq!(eval("2.0 ** 0.5"), Value::Number(2f64.powf(0.5)));
+        assert_eq!(eval("2.0 ** 0.5 == math.sqrt(2)"), Value::Boolean(true));
+        assert_eq!(eval("2 ** -2"), Value::Number(0.25));
+    }
+
+    #[test]
+    fn repr_of_a_string_reads_back_as_the_same_string() {
+        let original: String = (0..0x100).filter_map(char::from_u32).chain(['é', '\u{2028}', '🦀']).collect();
+        let mut interpreter = Interpreter::new();
+        interpreter.define_global("s", Value::String(original.clone()));
+        let repr = match crate::run("repr(s)".to_string(), &mut interpreter) {
+            Ok(Some(Value::String(repr))) => repr,
+            result => panic!("{:?}", result)
+        };
+        assert!(!repr.chars().any(|c| c.is_control()), "{}", repr);
+        assert_eq!(eval(&repr), Value::String(original));
+        assert_eq!(Value::String("a\u{1b}[0m\0".to_string()).repr(), "\"a\\u{1b}[0m\\u{0}\"");
+    }
+}
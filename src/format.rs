@@ -0,0 +1,363 @@
+use crate::grammar::{Expr, Stmt};
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::token::{Comment, Token};
+use crate::RloxError;
+
+pub struct FormatConfig {
+    pub indent: usize,
+    pub width: usize
+}
+
+impl Default for FormatConfig {
+    fn default() -> FormatConfig {
+        FormatConfig {
+            indent: 4,
+            width: 80
+        }
+    }
+}
+
+/// Reformats `source` in the canonical style, keeping its comments.
+pub fn format_source(source: &str, config: &FormatConfig) -> Result<String, Vec<RloxError>> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens()?;
+    let statements = Parser::new(tokens.clone()).parse()?;
+
+    let mut formatter = Formatter {
+        config,
+        tokens: &tokens,
+        comments: scanner.comments(),
+        cursor: 0,
+        next_comment: 0,
+        last_line: None,
+        out: String::new()
+    };
+    for stmt in &statements {
+        formatter.stmt(stmt, 0);
+    }
+    formatter.comments_before(tokens.len(), 0);
+    Ok(formatter.out)
+}
+
+/// Walks the AST and the token stream in step. The parser consumes tokens
+/// in source order, so every node knows exactly which tokens it came from.
+/// That recovers literal spellings like `0xFF` and places each comment.
+struct Formatter<'a> {
+    config: &'a FormatConfig,
+    tokens: &'a [Token],
+    comments: &'a [Comment],
+    cursor: usize,
+    next_comment: usize,
+    /// The last source line written out, used to keep blank lines.
+    last_line: Option<i32>,
+    out: String
+}
+
+impl<'a> Formatter<'a> {
+    fn stmt(&mut self, stmt: &Stmt, depth: usize) {
+        if let Stmt::Block(statements) = stmt {
+            return self.block(statements, depth);
+        }
+
+        let start = self.cursor;
+        let indent = depth * self.config.indent;
+        let text = self.stmt_text(stmt, indent);
+
+        // Comments inside a statement are moved above it.
+        self.comments_before(self.cursor, depth);
+        self.line(start, depth, &text);
+    }
+
+    fn stmt_text(&mut self, stmt: &Stmt, indent: usize) -> String {
+        match stmt {
+            Stmt::Echo(expr) => self.expr(expr, indent, indent),
+            Stmt::Expression(expr) => {
+                let text = self.expr(expr, indent, indent);
+                self.take();
+                format!("{};", text)
+            },
+            Stmt::Print(expr) => {
+                self.take();
+                let text = self.expr(expr, indent + "print ".len(), indent);
+                self.take();
+                format!("print {};", text)
+            },
+            Stmt::Var { annotation, initializer, .. } => {
+                self.take();
+                let mut name = self.take();
+                if annotation.is_some() {
+                    self.take();
+                    name = format!("{}: {}", name, self.take());
+                }
+                match initializer {
+                    Some(expr) => {
+                        self.take();
+                        let prefix = format!("var {} = ", name);
+                        let text = self.expr(expr, indent + prefix.len(), indent);
+                        self.take();
+                        format!("{}{};", prefix, text)
+                    },
+                    None => {
+                        self.take();
+                        format!("var {};", name)
+                    }
+                }
+            },
+            Stmt::Block(_) => String::new() // handled by `block`
+        }
+    }
+
+    fn block(&mut self, statements: &[Stmt], depth: usize) {
+        let open = self.cursor;
+        self.comments_before(open + 1, depth);
+        self.take();
+
+        if statements.is_empty() && !self.has_comment_before(self.cursor + 1) {
+            self.take();
+            return self.line(open, depth, "{}");
+        }
+
+        self.line(open, depth, "{");
+        for stmt in statements {
+            self.stmt(stmt, depth + 1);
+        }
+        let close = self.cursor;
+        self.comments_before(close + 1, depth + 1);
+        self.take();
+        self.line(close, depth, "}");
+    }
+
+    /// Renders an expression starting at column `column`. Continuation
+    /// lines are indented one level past `indent`.
+    fn expr(&mut self, expr: &Expr, column: usize, indent: usize) -> String {
+        let start = self.cursor;
+        let flat = self.flat(expr);
+        if column + flat.len() <= self.config.width {
+            return flat;
+        }
+
+        self.cursor = start;
+        let continuation = indent + self.config.indent;
+        let newline = format!("\n{}", " ".repeat(continuation));
+        match expr {
+            Expr::Binary { left, right, .. } => {
+                let left = self.expr(left, column, indent);
+                let operator = self.take();
+                let right = self.expr(right, continuation, indent);
+                if operator == "," {
+                    format!("{},{}{}", left, newline, right)
+                } else {
+                    format!("{} {}{}{}", left, operator, newline, right)
+                }
+            },
+            Expr::Conditional { condition, then_branch, else_branch, .. } => {
+                let condition = self.expr(condition, column, indent);
+                self.take();
+                let then_branch = self.expr(then_branch, continuation + 2, continuation);
+                self.take();
+                let else_branch = self.expr(else_branch, continuation + 2, continuation);
+                format!("{}{}? {}{}: {}", condition, newline, then_branch, newline, else_branch)
+            },
+            _ => {
+                self.cursor = start;
+                self.flat(expr)
+            }
+        }
+    }
+
+    fn flat(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Assign { value, .. } => {
+                let name = self.take();
+                let operator = self.take();
+                format!("{} {} {}", name, operator, self.flat(value))
+            },
+            Expr::Binary { left, right, .. } => {
+                let left = self.flat(left);
+                let operator = self.take();
+                let right = self.flat(right);
+                if operator == "," {
+                    format!("{}, {}", left, right)
+                } else {
+                    format!("{} {} {}", left, operator, right)
+                }
+            },
+            Expr::Call { callee, arguments, .. } => {
+                let callee = self.flat(callee);
+                format!("{}({})", callee, self.flat_items(arguments))
+            },
+            Expr::Conditional { condition, then_branch, else_branch, .. } => {
+                let condition = self.flat(condition);
+                self.take();
+                let then_branch = self.flat(then_branch);
+                self.take();
+                format!("{} ? {} : {}", condition, then_branch, self.flat(else_branch))
+            },
+            Expr::Get { object, .. } => {
+                let object = self.flat(object);
+                let dot = self.take();
+                format!("{}{}{}", object, dot, self.take())
+            },
+            Expr::Grouping(inner) => {
+                self.take();
+                let inner = self.flat(inner);
+                self.take();
+                format!("({})", inner)
+            },
+            Expr::Index { object, index, .. } => {
+                let object = self.flat(object);
+                format!("{}[{}]", object, self.flat_items(std::slice::from_ref(index.as_ref())))
+            },
+            Expr::List(elements) => format!("[{}]", self.flat_items(elements)),
+            Expr::Literal(_) | Expr::Variable(_) => self.take(),
+            Expr::Map { entries, .. } => {
+                self.take();
+                let mut items = Vec::new();
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.take();
+                    }
+                    let key = self.flat(key);
+                    self.take();
+                    items.push(format!("{}: {}", key, self.flat(value)));
+                }
+                self.take();
+                format!("{{{}}}", items.join(", "))
+            },
+            Expr::SetIndex { object, index, value, .. } => {
+                let object = self.flat(object);
+                let index = self.flat_items(std::slice::from_ref(index.as_ref()));
+                let operator = self.take();
+                format!("{}[{}] {} {}", object, index, operator, self.flat(value))
+            },
+            Expr::Unary { right, .. } => {
+                let operator = self.take();
+                let right = self.flat(right);
+                // `- -1`, not `--1`, which reads like a decrement.
+                let space = if right.starts_with(&operator) { " " } else { "" };
+                format!("{}{}{}", operator, space, right)
+            }
+        }
+    }
+
+    /// Renders comma-separated expressions between brackets, consuming the
+    /// brackets but leaving them out of the result.
+    fn flat_items(&mut self, exprs: &[Expr]) -> String {
+        self.take();
+        let mut items = Vec::new();
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.take();
+            }
+            items.push(self.flat(expr));
+        }
+        self.take();
+        items.join(", ")
+    }
+
+    /// Consumes the next token and returns its source text.
+    fn take(&mut self) -> String {
+        let lexeme = self.tokens.get(self.cursor).map(|token| token.details().lexeme).unwrap_or_default();
+        self.cursor += 1;
+        lexeme
+    }
+
+    fn token_line(&self, index: usize) -> i32 {
+        self.tokens.get(index).or_else(|| self.tokens.last()).map_or(1, |token| token.details().line)
+    }
+
+    fn has_comment_before(&self, token_index: usize) -> bool {
+        self.comments.get(self.next_comment).is_some_and(|comment| comment.token_index < token_index)
+    }
+
+    /// Writes every pending comment that comes before token `token_index`.
+    fn comments_before(&mut self, token_index: usize, depth: usize) {
+        while self.has_comment_before(token_index) {
+            let comment = &self.comments[self.next_comment];
+            self.next_comment += 1;
+            self.blank_line_before(comment.line);
+            self.out.push_str(&" ".repeat(depth * self.config.indent));
+            self.out.push_str(comment.text.trim_end());
+            self.out.push('\n');
+            self.last_line = Some(comment.line + comment.text.matches('\n').count() as i32);
+        }
+    }
+
+    /// Writes a line of code that started at token `start`, followed by any
+    /// comment that sat after it on the same source line.
+    fn line(&mut self, start: usize, depth: usize, text: &str) {
+        self.blank_line_before(self.token_line(start));
+        self.out.push_str(&" ".repeat(depth * self.config.indent));
+        self.out.push_str(text);
+
+        let end_line = self.token_line(self.cursor - 1);
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.token_index != self.cursor || comment.line != end_line {
+                break;
+            }
+            self.out.push_str("  ");
+            self.out.push_str(comment.text.trim_end());
+            self.next_comment += 1;
+        }
+        self.out.push('\n');
+        self.last_line = Some(end_line);
+    }
+
+    /// Keeps one blank line where the source had one or more.
+    fn blank_line_before(&mut self, line: i32) {
+        let after_open_brace = self.out.ends_with("{\n");
+        if let Some(last) = self.last_line {
+            if line > last + 1 && !after_open_brace {
+                self.out.push('\n');
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        format_source(source, &FormatConfig::default()).unwrap_or_else(|errs| panic!("{:?}", errs))
+    }
+
+    /// Formatting already formatted source changes nothing.
+    fn assert_idempotent(source: &str) -> String {
+        let formatted = format(source);
+        assert_eq!(format(&formatted), formatted, "formatting {:?} again changed it", formatted);
+        formatted
+    }
+
+    #[test]
+    fn adjacent_identical_unary_operators_are_spaced() {
+        assert_eq!(assert_idempotent("var x = - -1;"), "var x = - -1;\n");
+        assert_eq!(assert_idempotent("var x=-(-1);"), "var x = -(-1);\n");
+        assert_eq!(assert_idempotent("print !!true;"), "print ! !true;\n");
+        assert_eq!(assert_idempotent("print -~1;"), "print -~1;\n");
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let sources = [
+            "var a=1;var b = a+2*3 ;print a==b?a:b;",
+            "{ var x = [1,2,{\"k\": 3}]; x[0] += 1; print len(x) ; }",
+            "print math.sqrt( 2 ) ** 2, \"done\";",
+            "var long = 1111111111 + 2222222222 + 3333333333 + 4444444444 + 5555555555 + 6666666666;",
+            "{{}}\n\n\n{ print 1; }"
+        ];
+        for source in sources {
+            assert_idempotent(source);
+        }
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        let source = "// leading\nvar a = 1; // trailing\n/* block /* nested */ */\n{\n  // inside\n  print a;\n}\n";
+        let formatted = assert_idempotent(source);
+        for comment in ["// leading", "// trailing", "/* block /* nested */ */", "// inside"] {
+            assert!(formatted.contains(comment), "{:?} lost {:?}", formatted, comment);
+        }
+    }
+}
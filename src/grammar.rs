@@ -1,32 +1,174 @@
+use crate::scanner;
 use crate::token::{Token, Literal};
+use crate::visit::{walk_stmt, Visitor};
 use std::fmt;
 
 #[derive(Clone)]
 pub enum Expr {
+    Assign { name: Token, operator: Token, value: Box<Expr> },
     Binary { left: Box<Expr>, operator: Token, right: Box<Expr> },
+    /// `paren` is the closing `)`, where errors from the call are reported.
+    Call { callee: Box<Expr>, paren: Token, arguments: Vec<Expr> },
+    Conditional { condition: Box<Expr>, question: Token, then_branch: Box<Expr>, else_branch: Box<Expr> },
+    /// `object.name`.
+    Get { object: Box<Expr>, name: Token },
     Grouping(Box<Expr>),
+    /// `object[index]`. `bracket` is the closing `]`.
+    Index { object: Box<Expr>, bracket: Token, index: Box<Expr> },
+    List(Vec<Expr>),
     Literal(Literal),
-    Unary { operator: Token, right: Box<Expr> }
+    /// `{key: value, ...}`. `brace` is the closing `}`.
+    Map { brace: Token, entries: Vec<(Expr, Expr)> },
+    /// `object[index] = value`, or a compound assignment like `+=`.
+    SetIndex { object: Box<Expr>, bracket: Token, index: Box<Expr>, operator: Token, value: Box<Expr> },
+    Unary { operator: Token, right: Box<Expr> },
+    Variable(Token)
+}
+
+#[derive(Clone)]
+pub enum Stmt {
+    Block(Vec<Stmt>),
+    /// A trailing expression without a `;`. Its value is handed back to the
+    /// caller, so `1 + 2` on its own still prints `3`.
+    Echo(Expr),
+    Expression(Expr),
+    Print(Expr),
+    /// `annotation` is the type name after `:`, which only `rlox check` reads.
+    Var { name: Token, annotation: Option<Token>, initializer: Option<Expr> }
 }
 
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let string = match self {
-            Expr::Binary { left, operator, right } => parenthesize(&operator.details().lexeme, vec![left, right]),
-            Expr::Grouping(expr) => parenthesize("group", vec![expr]),
-            Expr::Literal(literal) => format!("{}", literal),
-            Expr::Unary { operator, right } => parenthesize(&operator.details().lexeme, vec![right])
-        };
-        write!(f, "{}", string)
+        let mut printer = Printer::default();
+        printer.visit_expr(self);
+        f.write_str(&printer.out)
+    }
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut printer = Printer::default();
+        printer.visit_stmt(self);
+        f.write_str(&printer.out)
+    }
+}
+
+/// Writes a tree as an s-expression, operator first.
+#[derive(Default)]
+struct Printer {
+    out: String
+}
+
+impl Printer {
+    fn parenthesize<'a>(&mut self, name: &str, exprs: impl IntoIterator<Item = &'a Expr>) {
+        self.out.push('(');
+        self.out.push_str(name);
+        for expr in exprs {
+            self.out.push(' ');
+            self.visit_expr(expr);
+        }
+        self.out.push(')');
     }
 }
 
-fn parenthesize(name: &str, exprs: Vec<&Expr>) -> String {
-    let mut string = String::from("(");
-    string.push_str(name);
-    for expr in exprs {
-        string.push_str(&format!(" {}", expr));
+impl Visitor for Printer {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) => self.parenthesize(";", [expr]),
+            Stmt::Print(expr) => self.parenthesize("print", [expr]),
+            _ => walk_stmt(self, stmt)
+        }
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) {
+        self.out.push_str("(block");
+        for stmt in statements {
+            self.out.push(' ');
+            self.visit_stmt(stmt);
+        }
+        self.out.push(')');
+    }
+
+    fn visit_var(&mut self, name: &Token, annotation: Option<&Token>, initializer: Option<&Expr>) {
+        self.out.push_str(&format!("(var {}", name.details().lexeme));
+        if let Some(annotation) = annotation {
+            self.out.push_str(&format!(": {}", annotation.details().lexeme));
+        }
+        if let Some(expr) = initializer {
+            self.out.push(' ');
+            self.visit_expr(expr);
+        }
+        self.out.push(')');
+    }
+
+    fn visit_assign(&mut self, name: &Token, operator: &Token, value: &Expr) {
+        self.out.push_str(&format!("({} {} ", operator.details().lexeme, name.details().lexeme));
+        self.visit_expr(value);
+        self.out.push(')');
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        self.parenthesize(&operator.details().lexeme, [left, right]);
+    }
+
+    fn visit_call(&mut self, callee: &Expr, _paren: &Token, arguments: &[Expr]) {
+        self.parenthesize("call", std::iter::once(callee).chain(arguments));
     }
-    string.push_str(")");
-    string
-}
\ No newline at end of file
+
+    fn visit_conditional(&mut self, condition: &Expr, _question: &Token, then_branch: &Expr, else_branch: &Expr) {
+        self.parenthesize("?:", [condition, then_branch, else_branch]);
+    }
+
+    fn visit_get(&mut self, object: &Expr, name: &Token) {
+        self.out.push_str("(. ");
+        self.visit_expr(object);
+        self.out.push_str(&format!(" {})", name.details().lexeme));
+    }
+
+    fn visit_grouping(&mut self, inner: &Expr) {
+        self.parenthesize("group", [inner]);
+    }
+
+    fn visit_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr) {
+        self.parenthesize("index", [object, index]);
+    }
+
+    fn visit_list(&mut self, elements: &[Expr]) {
+        self.parenthesize("list", elements);
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) {
+        match literal {
+            Literal::String(string) => self.out.push_str(&scanner::quote(string)),
+            _ => self.out.push_str(&literal.to_string())
+        }
+    }
+
+    fn visit_map(&mut self, _brace: &Token, entries: &[(Expr, Expr)]) {
+        self.out.push_str("(map");
+        for (key, value) in entries {
+            self.out.push_str(" (");
+            self.visit_expr(key);
+            self.out.push(' ');
+            self.visit_expr(value);
+            self.out.push(')');
+        }
+        self.out.push(')');
+    }
+
+    fn visit_set_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr, operator: &Token, value: &Expr) {
+        self.out.push_str(&format!("({} ", operator.details().lexeme));
+        self.parenthesize("index", [object, index]);
+        self.out.push(' ');
+        self.visit_expr(value);
+        self.out.push(')');
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) {
+        self.parenthesize(&operator.details().lexeme, [right]);
+    }
+
+    fn visit_variable(&mut self, name: &Token) {
+        self.out.push_str(&name.details().lexeme);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::scanner::Scanner;
+use crate::token::{SourcePiece, Token, TokenType};
+use std::env;
+use std::io::IsTerminal;
+
+const RESET: &str = "\x1b[0m";
+const KEYWORD: &str = "\x1b[35m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const OPERATOR: &str = "\x1b[36m";
+const COMMENT: &str = "\x1b[90m";
+const ERROR: &str = "\x1b[31m";
+
+/// Repaints the line being edited in colour. `linefeed` draws the buffer as
+/// plain text, so after each editing step the same characters are written
+/// again with colour codes and the cursor is put back where it was.
+pub struct Highlighter {
+    terminal: Option<mortal::Terminal>
+}
+
+impl Highlighter {
+    /// Highlighting is off when `NO_COLOR` is set, `TERM` is missing or
+    /// `dumb`, or stdout isn't a terminal.
+    pub fn new() -> Highlighter {
+        let dumb = env::var("TERM").map_or(true, |term| term.is_empty() || term == "dumb");
+        let enabled = !dumb && env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal();
+        Highlighter {
+            terminal: if enabled { mortal::Terminal::new().ok() } else { None }
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.terminal.is_some()
+    }
+
+    pub fn redraw(&self, prompt: &str, line: &str) {
+        let terminal = match &self.terminal {
+            Some(terminal) => terminal,
+            None => return
+        };
+
+        // Only lines that fit on one row are repainted. Anything that wraps or
+        // contains characters `linefeed` draws specially is left alone.
+        let fits = terminal.size().is_ok_and(|size| prompt.len() + line.len() < size.columns);
+        if !fits || line.is_empty() || !line.bytes().all(|b| (b' '..=b'~').contains(&b)) {
+            return;
+        }
+
+        let repaint = format!("\x1b7\r\x1b[{}C{}\x1b8", prompt.len(), highlight(line));
+        // Colour is cosmetic, so a failed write is ignored.
+        terminal.write_str(&repaint).ok();
+    }
+}
+
+/// Wraps the tokens of `line` in ANSI colour codes. The scanner's lossless
+/// mode also hands back comments and anything it rejected, so every
+/// character of the line is accounted for.
+pub fn highlight(line: &str) -> String {
+    let mut output = String::new();
+    for piece in Scanner::new(line.to_string()).scan_lossless() {
+        let text = piece.text();
+        let color = match &piece {
+            SourcePiece::Token(token) => token_color(token),
+            SourcePiece::Whitespace(_) => None,
+            SourcePiece::Comment(_) => Some(COMMENT),
+            // An unterminated string or comment still gets its own colour.
+            SourcePiece::Invalid(_) if text.starts_with('"') => Some(STRING),
+            SourcePiece::Invalid(_) if text.starts_with("/*") => Some(COMMENT),
+            SourcePiece::Invalid(_) => Some(ERROR)
+        };
+        match color {
+            Some(color) => output.push_str(&format!("{}{}{}", color, text, RESET)),
+            None => output.push_str(&text)
+        }
+    }
+    output
+}
+
+fn token_color(token: &Token) -> Option<&'static str> {
+    match token.details().token_type {
+        TokenType::String => Some(STRING),
+        TokenType::Number => Some(NUMBER),
+        TokenType::Identifier | TokenType::EOF => None,
+        TokenType::LeftParen | TokenType::RightParen | TokenType::LeftBrace | TokenType::RightBrace
+        | TokenType::LeftBracket | TokenType::RightBracket | TokenType::Comma | TokenType::Dot | TokenType::Semicolon => None,
+        TokenType::And | TokenType::Class | TokenType::Else | TokenType::False | TokenType::Fun
+        | TokenType::For | TokenType::If | TokenType::Nil | TokenType::Or | TokenType::Print
+        | TokenType::Return | TokenType::Super | TokenType::This | TokenType::True | TokenType::Var
+        | TokenType::While => Some(KEYWORD),
+        _ => Some(OPERATOR)
+    }
+}
@@ -0,0 +1,45 @@
+use crate::environment::Environment;
+use crate::eval::{Executable, Value};
+use crate::grammar::Stmt;
+use crate::natives::{MODULES, NATIVES};
+use crate::RloxError;
+
+/// Interpreter state that outlives a single `run`, so a REPL session keeps
+/// its variables from one line to the next.
+pub struct Interpreter {
+    environment: Environment
+}
+
+impl Interpreter {
+    /// Starts with only the built-in functions and modules defined.
+    pub fn new() -> Interpreter {
+        let mut environment = Environment::new();
+        for function in NATIVES {
+            environment.define_global(function.name, Value::Native(function));
+        }
+        for module in MODULES {
+            environment.define_global(module.name, Value::Module(module));
+        }
+        Interpreter { environment }
+    }
+}
+
+impl Interpreter {
+    /// Executes `statements` in order and returns the value of a trailing
+    /// `Stmt::Echo`, if there is one.
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<Option<Value>, RloxError> {
+        let mut last = None;
+        for stmt in statements {
+            last = stmt.execute(&mut self.environment)?;
+        }
+        Ok(last)
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.environment.define_global(name, value);
+    }
+
+    pub fn global_names(&self) -> Vec<String> {
+        self.environment.global_names()
+    }
+}
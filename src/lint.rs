@@ -0,0 +1,403 @@
+use crate::environment::Environment;
+use crate::eval::{Evaluable, Value};
+use crate::grammar::{Expr, Stmt};
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::token::{Comment, Literal, Token, TokenType};
+use crate::visit::{walk_expr, walk_expr_fold, walk_expr_mut, Folder, Visitor, VisitorMut};
+use crate::RloxError;
+use std::collections::HashSet;
+
+/// Every lint rule with a short description, as listed by `rlox lint --rules`.
+pub const RULES: &[(&str, &str)] = &[
+    ("unused-variable", "A variable is declared but never read"),
+    ("shadowed-local", "A block declares a variable that hides one from an enclosing scope"),
+    ("truthiness-comparison", "Comparing with nil, true or false where a truthiness test was likely meant"),
+    ("self-comparison", "Both sides of a comparison are the same expression"),
+    ("constant-condition", "A conditional expression's condition never changes"),
+    ("division-by-zero", "Dividing by a literal zero")
+];
+
+/// Rules that wait on a language feature, listed by `--rules` so nobody
+/// goes looking for them. They can't be named in a config file yet.
+pub const PLANNED_RULES: &[(&str, &str)] = &[
+    ("unreachable-code", "Statements after a return; waits on `return` being parsed")
+];
+
+/// The file read from the working directory when `--config` isn't given.
+pub const DEFAULT_CONFIG_FILE: &str = ".rloxlint";
+
+#[derive(Default)]
+pub struct LintConfig {
+    disabled: HashSet<String>
+}
+
+impl LintConfig {
+    /// Parses a config file of `rule = on|off` lines. `#` starts a comment.
+    pub fn parse(text: &str) -> Result<LintConfig, String> {
+        let mut config = LintConfig::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (rule, setting) = line.split_once('=')
+                .map(|(rule, setting)| (rule.trim(), setting.trim()))
+                .ok_or_else(|| format!("line {}: Expect 'rule = on' or 'rule = off'.", number + 1))?;
+            if !RULES.iter().any(|(name, _)| *name == rule) {
+                return Err(format!("line {}: Unknown rule '{}'.", number + 1, rule));
+            }
+            match setting {
+                "on" => config.disabled.remove(rule),
+                "off" => config.disabled.insert(rule.to_string()),
+                _ => return Err(format!("line {}: Expect 'on' or 'off' for '{}'.", number + 1, rule))
+            };
+        }
+        Ok(config)
+    }
+}
+
+pub struct Warning {
+    pub rule: &'static str,
+    pub line: i32,
+    pub message: String
+}
+
+/// Lints `source`, returning warnings in line order. Rules turned off in
+/// `config`, or named in a `// rlox-allow(rule)` comment on the line of a
+/// warning or the line above it, are left out.
+pub fn lint_source(source: &str, config: &LintConfig) -> Result<Vec<Warning>, Vec<RloxError>> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens()?;
+    let statements = Parser::new(tokens).parse()?;
+
+    let mut linter = Linter { scopes: vec![Vec::new()], warnings: Vec::new() };
+    for stmt in &statements {
+        linter.visit_stmt(stmt);
+    }
+    linter.end_scope();
+
+    let mut warnings: Vec<Warning> = linter.warnings.into_iter()
+        .filter(|warning| !config.disabled.contains(warning.rule))
+        .filter(|warning| !is_allowed(scanner.comments(), warning))
+        .collect();
+    warnings.sort_by_key(|warning| warning.line);
+    Ok(warnings)
+}
+
+fn is_allowed(comments: &[Comment], warning: &Warning) -> bool {
+    comments.iter()
+        .filter(|comment| comment.line == warning.line || comment.line == warning.line - 1)
+        .any(|comment| allowed_rules(&comment.text).any(|rule| rule == warning.rule))
+}
+
+/// The rules named in every `rlox-allow(a, b)` inside a comment.
+fn allowed_rules(comment: &str) -> impl Iterator<Item = &str> {
+    comment.split("rlox-allow(").skip(1)
+        .filter_map(|rest| rest.split_once(')').map(|(rules, _)| rules))
+        .flat_map(|rules| rules.split(',').map(str::trim))
+}
+
+struct Declaration {
+    name: String,
+    line: i32,
+    used: bool
+}
+
+struct Linter {
+    /// Declarations in each open scope, globals first.
+    scopes: Vec<Vec<Declaration>>,
+    warnings: Vec<Warning>
+}
+
+impl Visitor for Linter {
+    fn visit_block(&mut self, statements: &[Stmt]) {
+        self.scopes.push(Vec::new());
+        statements.iter().for_each(|stmt| self.visit_stmt(stmt));
+        self.end_scope();
+    }
+
+    fn visit_var(&mut self, name: &Token, _annotation: Option<&Token>, initializer: Option<&Expr>) {
+        if let Some(expr) = initializer {
+            self.visit_expr(expr);
+        }
+        self.declare(name);
+    }
+
+    fn visit_assign(&mut self, _name: &Token, operator: &Token, value: &Expr) {
+        if operator.details().token_type == TokenType::SlashEqual {
+            self.check_divisor(operator, value);
+        }
+        self.visit_expr(value);
+    }
+
+    fn visit_set_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr, operator: &Token, value: &Expr) {
+        if operator.details().token_type == TokenType::SlashEqual {
+            self.check_divisor(operator, value);
+        }
+        self.visit_expr(object);
+        self.visit_expr(index);
+        self.visit_expr(value);
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        self.check_binary(left, operator, right);
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+
+    fn visit_conditional(&mut self, condition: &Expr, question: &Token, then_branch: &Expr, else_branch: &Expr) {
+        self.visit_expr(condition);
+        self.check_condition(condition, question);
+        self.visit_expr(then_branch);
+        self.visit_expr(else_branch);
+    }
+
+    fn visit_variable(&mut self, name: &Token) {
+        self.use_variable(name);
+    }
+}
+
+impl Linter {
+    fn declare(&mut self, name: &Token) {
+        let details = name.details();
+        let (current, enclosing) = self.scopes.split_last_mut().expect("Linter has no scope.");
+        if !enclosing.is_empty() {
+            let outer = enclosing.iter().rev().find_map(|scope| scope.iter().rev().find(|d| d.name == details.lexeme));
+            if let Some(outer) = outer {
+                let message = format!("'{}' shadows the variable declared on line {}.", details.lexeme, outer.line);
+                self.warnings.push(Warning { rule: "shadowed-local", line: details.line, message });
+            }
+        }
+        current.push(Declaration { name: details.lexeme, line: details.line, used: false });
+    }
+
+    fn use_variable(&mut self, name: &Token) {
+        let lexeme = name.details().lexeme;
+        let declaration = self.scopes.iter_mut().rev()
+            .find_map(|scope| scope.iter_mut().rev().find(|declaration| declaration.name == lexeme));
+        if let Some(declaration) = declaration {
+            declaration.used = true;
+        }
+    }
+
+    fn end_scope(&mut self) {
+        for declaration in self.scopes.pop().unwrap_or_default() {
+            if !declaration.used {
+                let message = format!("'{}' is never read.", declaration.name);
+                self.warnings.push(Warning { rule: "unused-variable", line: declaration.line, message });
+            }
+        }
+    }
+
+    fn check_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        let details = operator.details();
+        match details.token_type {
+            TokenType::Slash | TokenType::Percent => self.check_divisor(operator, right),
+            TokenType::EqualEqual | TokenType::BangEqual => {
+                for side in [left, right] {
+                    if let Expr::Literal(literal @ (Literal::Nil | Literal::True | Literal::False)) = side {
+                        let message = truthiness_message(literal, &details.lexeme);
+                        self.warnings.push(Warning { rule: "truthiness-comparison", line: details.line, message });
+                        break;
+                    }
+                }
+            },
+            _ => ()
+        }
+
+        let comparison = matches!(details.token_type,
+            TokenType::EqualEqual | TokenType::BangEqual | TokenType::Greater
+            | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual);
+        if comparison && !has_side_effects(left) && ungrouped(left).to_string() == ungrouped(right).to_string() {
+            let message = format!("Both sides of '{}' are the same expression.", details.lexeme);
+            self.warnings.push(Warning { rule: "self-comparison", line: details.line, message });
+        }
+    }
+
+    fn check_divisor(&mut self, operator: &Token, divisor: &Expr) {
+        let divisor = match divisor {
+            Expr::Grouping(inner) => inner,
+            _ => divisor
+        };
+        let is_zero = match divisor {
+            Expr::Literal(Literal::Int(n)) => *n == 0,
+            Expr::Literal(Literal::Number(n)) => *n == 0.0,
+            _ => false
+        };
+        if is_zero {
+            let details = operator.details();
+            let operation = if details.token_type == TokenType::Percent { "Modulo" } else { "Division" };
+            let message = format!("{} by a literal zero.", operation);
+            self.warnings.push(Warning { rule: "division-by-zero", line: details.line, message });
+        }
+    }
+
+    /// A condition that folds down to a literal always has the same value.
+    fn check_condition(&mut self, condition: &Expr, question: &Token) {
+        if let Expr::Literal(literal) = ConstantFolder.fold_expr(condition.clone()) {
+            let message = format!("The condition is always {}.", Value::from(&literal).is_truthy());
+            self.warnings.push(Warning { rule: "constant-condition", line: question.details().line, message });
+        }
+    }
+}
+
+fn truthiness_message(literal: &Literal, operator: &str) -> String {
+    match (literal, operator) {
+        (Literal::Nil, "==") => "'== nil' doesn't match false; use '!' to test for a falsey value.".to_string(),
+        (Literal::Nil, _) => "'!= nil' is also true for false; test the value itself for truthiness.".to_string(),
+        _ => format!("Comparing with {} only matches {} itself; test the value itself for truthiness.", literal, literal)
+    }
+}
+
+fn has_side_effects(expr: &Expr) -> bool {
+    any_node(expr, |expr| matches!(expr, Expr::Assign { .. } | Expr::Call { .. } | Expr::SetIndex { .. }))
+}
+
+fn uses_variables(expr: &Expr) -> bool {
+    any_node(expr, |expr| has_side_effects(expr) || matches!(expr, Expr::Variable(_)))
+}
+
+/// Whether `test` holds for `expr` or any expression inside it.
+fn any_node(expr: &Expr, test: fn(&Expr) -> bool) -> bool {
+    struct Search {
+        test: fn(&Expr) -> bool,
+        found: bool
+    }
+
+    impl Visitor for Search {
+        fn visit_expr(&mut self, expr: &Expr) {
+            if (self.test)(expr) {
+                self.found = true;
+            } else if !self.found {
+                walk_expr(self, expr);
+            }
+        }
+    }
+
+    let mut search = Search { test, found: false };
+    search.visit_expr(expr);
+    search.found
+}
+
+/// `expr` without its parentheses, which the tree's shape already
+/// accounts for, so `(a) == a` compares equal expressions.
+fn ungrouped(expr: &Expr) -> Expr {
+    struct Ungroup;
+
+    impl VisitorMut for Ungroup {
+        fn visit_expr_mut(&mut self, expr: &mut Expr) {
+            walk_expr_mut(self, expr);
+            if let Expr::Grouping(inner) = expr {
+                let inner = std::mem::replace(inner.as_mut(), Expr::Literal(Literal::Nil));
+                *expr = inner;
+            }
+        }
+    }
+
+    let mut expr = expr.clone();
+    Ungroup.visit_expr_mut(&mut expr);
+    expr
+}
+
+/// Replaces operators whose operands are all literals with the literal
+/// they evaluate to. Anything that fails to evaluate is left as it is.
+struct ConstantFolder;
+
+impl Folder for ConstantFolder {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        let expr = walk_expr_fold(self, expr);
+        if uses_variables(&expr) || matches!(expr, Expr::Literal(_)) {
+            return expr;
+        }
+        let literal = match expr.evaluate(&mut Environment::new()) {
+            Ok(Value::String(string)) => Literal::String(string),
+            Ok(Value::Int(n)) => Literal::Int(n),
+            Ok(Value::Number(n)) => Literal::Number(n),
+            Ok(Value::Boolean(true)) => Literal::True,
+            Ok(Value::Boolean(false)) => Literal::False,
+            Ok(Value::Nil) => Literal::Nil,
+            _ => return expr
+        };
+        Expr::Literal(literal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every warning as `line rule`.
+    fn lint(source: &str) -> Vec<String> {
+        lint_with(source, "")
+    }
+
+    fn lint_with(source: &str, config: &str) -> Vec<String> {
+        let config = LintConfig::parse(config).unwrap();
+        let warnings = lint_source(source, &config).unwrap_or_else(|_| panic!("'{}' doesn't parse", source));
+        warnings.iter().map(|warning| format!("{} {}", warning.line, warning.rule)).collect()
+    }
+
+    #[test]
+    fn unused_variable() {
+        assert_eq!(lint("var a = 1;\nvar b = 2;\nprint b;"), ["1 unused-variable"]);
+        assert_eq!(lint("var a = 1;\n{ print a; }"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn shadowed_local() {
+        assert_eq!(lint("var a = 1;\n{\n  var a = 2;\n  print a;\n}\nprint a;"), ["3 shadowed-local"]);
+        assert_eq!(lint("{ var a = 1; print a; }\n{ var a = 2; print a; }"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn truthiness_comparison() {
+        assert_eq!(lint("var a;\nprint a == nil;\nprint true != a;"), ["2 truthiness-comparison", "3 truthiness-comparison"]);
+        assert_eq!(lint("var a;\nprint !a;"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn self_comparison() {
+        assert_eq!(lint("var a = 1;\nprint (a) <= a;"), ["2 self-comparison"]);
+        // A call might return something different each time.
+        assert_eq!(lint("print clock() == clock();"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn constant_condition_is_reported_on_the_line_of_its_question_mark() {
+        assert_eq!(lint("print 1 < 2\n  ? \"yes\"\n  : \"no\";"), ["2 constant-condition"]);
+        assert_eq!(lint("var a = 1;\nprint (nil ? 1 : 2) ? a : 3;\nprint a\n  ? (a ? 1 : 2)\n  : (false ? 3 : 4);"), [
+            "2 constant-condition", "2 constant-condition", "5 constant-condition"
+        ]);
+        assert_eq!(lint("var a = 1;\nprint a > 0 ? 1 : 2;"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(lint("var a = 1;\nprint a / 0;\nprint a % (0.0);\na /= 0;"), [
+            "2 division-by-zero", "3 division-by-zero", "4 division-by-zero"
+        ]);
+        assert_eq!(lint("var a = 1;\nprint a / 2;"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rules_can_be_turned_off() {
+        let source = "var a = 1;\nprint a / 0;\nvar b;";
+        assert_eq!(lint_with(source, "# no noise\ndivision-by-zero = off\nunused-variable = off\nunused-variable = on"), ["3 unused-variable"]);
+        assert_eq!(lint_with(source, "division-by-zero=off\nunused-variable=off"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rules_can_be_allowed_inline() {
+        let source = "var a = 1;\n// rlox-allow(division-by-zero)\nprint a / 0;\nprint a / 0; // rlox-allow(self-comparison, division-by-zero)\n\nprint a / 0;";
+        assert_eq!(lint(source), ["6 division-by-zero"]);
+    }
+
+    #[test]
+    fn bad_configs_are_rejected() {
+        assert_eq!(LintConfig::parse("nonsense").err().unwrap(), "line 1: Expect 'rule = on' or 'rule = off'.");
+        assert_eq!(LintConfig::parse("\nunused = off").err().unwrap(), "line 2: Unknown rule 'unused'.");
+        assert_eq!(LintConfig::parse("unused-variable = maybe").err().unwrap(), "line 1: Expect 'on' or 'off' for 'unused-variable'.");
+    }
+}
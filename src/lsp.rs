@@ -0,0 +1,574 @@
+//! `rlox lsp`: a language server speaking JSON-RPC over standard input and
+//! output. Every request is answered from the lossless syntax tree of the
+//! latest text of the document, so it works on code that doesn't parse.
+
+use crate::eval::Value;
+use crate::json::Json;
+use crate::scanner::Scanner;
+use crate::syntax::{self, SyntaxKind, SyntaxNode, SyntaxToken};
+use crate::token::{Token, TokenType};
+use crate::RloxError;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_PARAMS: i32 = -32602;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// Semantic token types, in the order their indexes are sent to the client.
+const TOKEN_TYPES: &[&str] = &["keyword", "string", "number", "operator", "variable", "comment"];
+
+/// `SymbolKind.Variable` in the protocol.
+const VARIABLE_SYMBOL: usize = 13;
+
+/// Serves the client on stdin and stdout. Returns the process exit code.
+pub fn run() -> i32 {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    serve(stdin.lock(), stdout.lock()).unwrap_or_else(|e| {
+        eprintln!("rlox: Language server I/O failed: {}", e);
+        1
+    })
+}
+
+/// Serves one client until it sends `exit`. Any reader and writer will do,
+/// so a scripted client can drive the server through a pipe or a file.
+/// Returns 0 if the client asked to shut down first, as the protocol says.
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<i32> {
+    let mut server = Server { documents: HashMap::new(), shutdown: false };
+
+    while let Some(body) = read_message(&mut input)? {
+        let message = match Json::parse(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                write_message(&mut output, &error_response(Json::Null, PARSE_ERROR, &e))?;
+                continue;
+            }
+        };
+
+        let id = message.get("id").clone();
+        let params = message.get("params");
+        match message.get("method").as_str() {
+            Some("exit") => return Ok(if server.shutdown { 0 } else { 1 }),
+            // A response to something we never asked.
+            None => (),
+            Some(method) if id.is_null() => {
+                for notification in server.notify(method, params) {
+                    write_message(&mut output, &notification)?;
+                }
+            },
+            Some(method) => {
+                let response = match server.request(method, params) {
+                    Ok(result) => Json::object(vec![("jsonrpc", "2.0".into()), ("id", id), ("result", result)]),
+                    Err((code, message)) => error_response(id, code, &message)
+                };
+                write_message(&mut output, &response)?;
+            }
+        }
+    }
+
+    // The client went away without sending `exit`.
+    Ok(1)
+}
+
+/// Reads one message body, or `None` at the end of input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header."))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn error_response(id: Json, code: i32, message: &str) -> Json {
+    let error = Json::object(vec![("code", Json::Int(code.into())), ("message", message.into())]);
+    Json::object(vec![("jsonrpc", "2.0".into()), ("id", id), ("error", error)])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![("jsonrpc", "2.0".into()), ("method", method.into()), ("params", params)])
+}
+
+struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool
+}
+
+impl Server {
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, (i32, String)> {
+        match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            },
+            "textDocument/hover" => {
+                let (document, offset) = self.position(params)?;
+                Ok(document.hover(offset))
+            },
+            "textDocument/definition" => {
+                let (document, offset) = self.position(params)?;
+                Ok(match document.symbol_at(offset) {
+                    Some(symbol) => document.location(&symbol.declaration),
+                    None => Json::Null
+                })
+            },
+            "textDocument/references" => {
+                let (document, offset) = self.position(params)?;
+                let include_declaration = params.get("context").get("includeDeclaration").as_bool().unwrap_or(true);
+                let symbol = match document.symbol_at(offset) {
+                    Some(symbol) => symbol,
+                    None => return Ok(Json::Array(Vec::new()))
+                };
+                let declaration = if include_declaration { Some(&symbol.declaration) } else { None };
+                let locations = declaration.into_iter().chain(&symbol.references)
+                    .map(|range| document.location(range))
+                    .collect();
+                Ok(Json::Array(locations))
+            },
+            "textDocument/documentSymbol" => Ok(self.document(params)?.document_symbols()),
+            "textDocument/semanticTokens/full" => Ok(self.document(params)?.semantic_tokens()),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'.", method)))
+        }
+    }
+
+    /// Handles a notification, returning any notifications to send back.
+    fn notify(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("").to_string();
+        let text = match method {
+            "textDocument/didOpen" => params.get("textDocument").get("text").as_str(),
+            // Only full-text sync is offered, so the last change is the whole document.
+            "textDocument/didChange" => params.get("contentChanges").as_array().last()
+                .and_then(|change| change.get("text").as_str()),
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![publish_diagnostics(&uri, Vec::new())];
+            },
+            _ => return Vec::new()
+        };
+
+        match text {
+            Some(text) => {
+                let document = Document::new(uri.clone(), text.to_string());
+                let diagnostics = document.diagnostics();
+                self.documents.insert(uri.clone(), document);
+                vec![publish_diagnostics(&uri, diagnostics)]
+            },
+            None => Vec::new()
+        }
+    }
+
+    fn document(&self, params: &Json) -> Result<&Document, (i32, String)> {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+        self.documents.get(uri).ok_or_else(|| (INVALID_PARAMS, format!("Unknown document '{}'.", uri)))
+    }
+
+    fn position(&self, params: &Json) -> Result<(&Document, usize), (i32, String)> {
+        let document = self.document(params)?;
+        let position = params.get("position");
+        match (position.get("line").as_usize(), position.get("character").as_usize()) {
+            (Some(line), Some(character)) => Ok((document, document.offset(line, character))),
+            _ => Err((INVALID_PARAMS, "Expect a position.".to_string()))
+        }
+    }
+}
+
+fn capabilities() -> Json {
+    let legend = Json::object(vec![
+        ("tokenTypes", Json::Array(TOKEN_TYPES.iter().map(|&name| name.into()).collect())),
+        ("tokenModifiers", Json::Array(Vec::new()))
+    ]);
+    let capabilities = Json::object(vec![
+        ("textDocumentSync", Json::Int(1)),
+        ("hoverProvider", true.into()),
+        ("definitionProvider", true.into()),
+        ("referencesProvider", true.into()),
+        ("documentSymbolProvider", true.into()),
+        ("semanticTokensProvider", Json::object(vec![("legend", legend), ("full", true.into())]))
+    ]);
+    let server_info = Json::object(vec![("name", "rlox".into()), ("version", env!("CARGO_PKG_VERSION").into())]);
+    Json::object(vec![("capabilities", capabilities), ("serverInfo", server_info)])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    let params = Json::object(vec![("uri", uri.into()), ("diagnostics", Json::Array(diagnostics))]);
+    notification("textDocument/publishDiagnostics", params)
+}
+
+/// A variable declaration and every use of it, as byte ranges of the name.
+struct Symbol {
+    name: String,
+    declaration: Range<usize>,
+    /// The whole `var` statement.
+    statement: Range<usize>,
+    references: Vec<Range<usize>>
+}
+
+struct Document {
+    uri: String,
+    text: String,
+    tree: SyntaxNode,
+    symbols: Vec<Symbol>,
+    /// Byte offset where each line starts.
+    line_starts: Vec<usize>
+}
+
+impl Document {
+    fn new(uri: String, text: String) -> Document {
+        let tree = syntax::parse(&text);
+        // Ranges are computed from token offsets, which only line up with
+        // the text if the tree covers every byte of it.
+        debug_assert_eq!(tree.text(), text);
+        let symbols = resolve(&tree);
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Document { uri, text, tree, symbols, line_starts }
+    }
+
+    /// Scanner and parser errors. They only carry a line, so the range is
+    /// narrowed to the offending lexeme when it can be found on that line.
+    fn diagnostics(&self) -> Vec<Json> {
+        let errs = match crate::parse(self.text.clone()) {
+            Ok(_) => return Vec::new(),
+            Err(errs) => errs
+        };
+        errs.iter().map(|err| {
+            Json::object(vec![
+                ("range", self.range(&self.error_range(err))),
+                ("severity", Json::Int(1)),
+                ("source", "rlox".into()),
+                ("message", err.message.clone().into())
+            ])
+        }).collect()
+    }
+
+    fn error_range(&self, err: &RloxError) -> Range<usize> {
+        let line = (err.line.unwrap_or(1).max(1) as usize - 1).min(self.line_starts.len() - 1);
+        let start = self.line_starts[line];
+        let end = self.text[start..].find('\n').map_or(self.text.len(), |i| start + i);
+
+        let lexeme = err.location.strip_prefix(" at '")
+            .and_then(|rest| rest.rfind('\'').map(|quote| &rest[..quote]))
+            .filter(|lexeme| !lexeme.is_empty());
+        match lexeme.and_then(|lexeme| self.text[start..end].find(lexeme).map(|i| (i, lexeme.len()))) {
+            Some((i, len)) => start + i..start + i + len,
+            None => start..end
+        }
+    }
+
+    /// The type of the literal under the cursor.
+    fn hover(&self, offset: usize) -> Json {
+        let token = match self.token_at(offset) {
+            Some(token) => token,
+            None => return Json::Null
+        };
+        let type_name = match Scanner::new(token.text().to_string()).scan_tokens().ok().as_deref() {
+            Some([Token::Literal(_, literal), ..]) => Value::from(literal).type_name(),
+            _ => return Json::Null
+        };
+        let contents = Json::object(vec![("kind", "plaintext".into()), ("value", format!("{} literal", type_name).into())]);
+        Json::object(vec![("contents", contents), ("range", self.range(&token.text_range()))])
+    }
+
+    /// The token under the cursor, or the one just before it.
+    fn token_at(&self, offset: usize) -> Option<SyntaxToken> {
+        let tokens = self.tree.descendant_tokens();
+        let is_code = |token: &&SyntaxToken| matches!(token.kind(), SyntaxKind::Token(_));
+        let inside = tokens.iter().filter(is_code).find(|token| token.text_range().contains(&offset));
+        let before = || tokens.iter().filter(is_code).find(|token| token.text_range().end == offset);
+        inside.or_else(before).cloned()
+    }
+
+    fn symbol_at(&self, offset: usize) -> Option<&Symbol> {
+        let touches = |range: &Range<usize>| range.start <= offset && offset <= range.end;
+        self.symbols.iter()
+            .find(|symbol| touches(&symbol.declaration) || symbol.references.iter().any(touches))
+    }
+
+    /// Functions and classes aren't in the language yet, so the outline is
+    /// the variables, nested ones included.
+    fn document_symbols(&self) -> Json {
+        Json::Array(self.symbols.iter().map(|symbol| {
+            Json::object(vec![
+                ("name", symbol.name.clone().into()),
+                ("kind", VARIABLE_SYMBOL.into()),
+                ("range", self.range(&symbol.statement)),
+                ("selectionRange", self.range(&symbol.declaration))
+            ])
+        }).collect())
+    }
+
+    /// Encodes each token as five numbers relative to the previous one:
+    /// line delta, start delta, length, type and modifiers. Tokens spanning
+    /// several lines are split, since clients needn't accept them whole.
+    fn semantic_tokens(&self) -> Json {
+        let mut data = Vec::new();
+        let (mut last_line, mut last_start) = (0, 0);
+        for token in self.tree.descendant_tokens() {
+            let token_type = match semantic_token_type(token.kind()) {
+                Some(token_type) => token_type,
+                None => continue
+            };
+
+            let mut start = token.text_range().start;
+            for segment in token.text().split('\n') {
+                let text = segment.trim_end_matches('\r');
+                if !text.is_empty() {
+                    let (line, character) = self.position(start);
+                    let delta_start = if line == last_line { character - last_start } else { character };
+                    data.extend([line - last_line, delta_start, text.encode_utf16().count(), token_type, 0]);
+                    last_line = line;
+                    last_start = character;
+                }
+                start += segment.len() + 1;
+            }
+        }
+        Json::object(vec![("data", Json::Array(data.into_iter().map(Json::from).collect()))])
+    }
+
+    fn location(&self, range: &Range<usize>) -> Json {
+        Json::object(vec![("uri", self.uri.clone().into()), ("range", self.range(range))])
+    }
+
+    fn range(&self, range: &Range<usize>) -> Json {
+        let position = |offset| {
+            let (line, character) = self.position(offset);
+            Json::object(vec![("line", line.into()), ("character", character.into())])
+        };
+        Json::object(vec![("start", position(range.start)), ("end", position(range.end))])
+    }
+
+    /// The line and UTF-16 column of a byte offset, as the protocol counts them.
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.line_starts[line]..offset].encode_utf16().count();
+        (line, character)
+    }
+
+    fn offset(&self, line: usize, character: usize) -> usize {
+        let start = match self.line_starts.get(line) {
+            Some(&start) => start,
+            None => return self.text.len()
+        };
+
+        let mut units = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if units >= character || c == '\n' {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+}
+
+fn semantic_token_type(kind: SyntaxKind) -> Option<usize> {
+    let name = match kind {
+        SyntaxKind::Comment => "comment",
+        SyntaxKind::Token(token_type) => match token_type {
+            TokenType::String => "string",
+            TokenType::Number => "number",
+            TokenType::Identifier => "variable",
+            TokenType::LeftParen | TokenType::RightParen | TokenType::LeftBrace | TokenType::RightBrace
+            | TokenType::LeftBracket | TokenType::RightBracket | TokenType::Comma | TokenType::Dot | TokenType::Semicolon | TokenType::EOF => return None,
+            TokenType::And | TokenType::Class | TokenType::Else | TokenType::False | TokenType::Fun
+            | TokenType::For | TokenType::If | TokenType::Nil | TokenType::Or | TokenType::Print
+            | TokenType::Return | TokenType::Super | TokenType::This | TokenType::True | TokenType::Var
+            | TokenType::While => "keyword",
+            _ => "operator"
+        },
+        _ => return None
+    };
+    TOKEN_TYPES.iter().position(|&token_type| token_type == name)
+}
+
+/// Matches each variable use to its declaration, following block scopes
+/// the way the interpreter does. A name not found in any open scope falls
+/// back to a global declared anywhere in the file.
+fn resolve(tree: &SyntaxNode) -> Vec<Symbol> {
+    let mut resolver = Resolver { scopes: vec![Vec::new()], symbols: Vec::new(), unresolved: Vec::new() };
+    resolver.node(tree);
+
+    for (name, range) in std::mem::take(&mut resolver.unresolved) {
+        if let Some(index) = resolver.lookup_in(0, &name) {
+            resolver.symbols[index].references.push(range);
+        }
+    }
+    resolver.symbols
+}
+
+struct Resolver {
+    /// Names declared in each open scope with their symbol index, globals first.
+    scopes: Vec<Vec<(String, usize)>>,
+    symbols: Vec<Symbol>,
+    unresolved: Vec<(String, Range<usize>)>
+}
+
+impl Resolver {
+    fn node(&mut self, node: &SyntaxNode) {
+        match node.kind() {
+            SyntaxKind::Block => {
+                self.scopes.push(Vec::new());
+                node.child_nodes().iter().for_each(|child| self.node(child));
+                self.scopes.pop();
+            },
+            SyntaxKind::VarDecl => {
+                // The initializer can't see the variable it initializes.
+                node.child_nodes().iter().for_each(|child| self.node(child));
+                if let Some(name) = identifier(node) {
+                    self.symbols.push(Symbol {
+                        name: name.text().to_string(),
+                        declaration: name.text_range(),
+                        statement: node.text_range(),
+                        references: Vec::new()
+                    });
+                    let index = self.symbols.len() - 1;
+                    self.scopes.last_mut().expect("Resolver has no scope.").push((name.text().to_string(), index));
+                }
+            },
+            SyntaxKind::VariableExpr => {
+                if let Some(name) = identifier(node) {
+                    let found = (0..self.scopes.len()).rev().find_map(|depth| self.lookup_in(depth, name.text()));
+                    match found {
+                        Some(index) => self.symbols[index].references.push(name.text_range()),
+                        None => self.unresolved.push((name.text().to_string(), name.text_range()))
+                    }
+                }
+            },
+            _ => node.child_nodes().iter().for_each(|child| self.node(child))
+        }
+    }
+
+    fn lookup_in(&self, depth: usize, name: &str) -> Option<usize> {
+        self.scopes[depth].iter().rev().find(|(declared, _)| declared == name).map(|(_, index)| *index)
+    }
+}
+
+fn identifier(node: &SyntaxNode) -> Option<SyntaxToken> {
+    node.child_tokens().into_iter().find(|token| token.kind() == SyntaxKind::Token(TokenType::Identifier))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const URI: &str = "file:///test.lox";
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    fn request(id: usize, method: &str, params: &str) -> String {
+        frame(&format!(r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{}}}"#, id, method, params))
+    }
+
+    fn position(line: usize, character: usize) -> String {
+        format!(r#"{{"textDocument":{{"uri":"{}"}},"position":{{"line":{},"character":{}}}}}"#, URI, line, character)
+    }
+
+    /// Runs a session and returns the exit code and every message the
+    /// server wrote, unframed.
+    fn session(messages: &[String]) -> (i32, Vec<Json>) {
+        let mut output = Vec::new();
+        let code = serve(Cursor::new(messages.concat()), &mut output).unwrap();
+        let mut output = Cursor::new(output);
+        let mut replies = Vec::new();
+        while let Some(body) = read_message(&mut output).unwrap() {
+            replies.push(Json::parse(&body).unwrap());
+        }
+        (code, replies)
+    }
+
+    fn result(replies: &[Json], id: usize) -> &Json {
+        replies.iter().find(|reply| reply.get("id").as_usize() == Some(id)).unwrap().get("result")
+    }
+
+    #[test]
+    fn serves_a_scripted_session() {
+        let text = "var answer = 42;\\nprint answer;\\nprint ;\\n";
+        let did_open = format!(
+            r#"{{"languageId":"lox","version":1,"uri":"{}","text":"{}"}}"#, URI, text);
+        let document = format!(r#"{{"textDocument":{{"uri":"{}"}}}}"#, URI);
+        let (code, replies) = session(&[
+            request(1, "initialize", "{}"),
+            frame(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#),
+            frame(&format!(r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{}}}}}"#, did_open)),
+            request(2, "textDocument/hover", &position(0, 14)),
+            request(3, "textDocument/definition", &position(1, 8)),
+            request(4, "textDocument/references", &position(0, 6)),
+            request(5, "textDocument/documentSymbol", &document),
+            request(6, "textDocument/semanticTokens/full", &document),
+            request(7, "shutdown", "null"),
+            frame(r#"{"jsonrpc":"2.0","method":"exit"}"#)
+        ]);
+        assert_eq!(code, 0);
+
+        let range = |line: usize, start: usize, end: usize| format!(
+            r#"{{"start":{{"line":{},"character":{}}},"end":{{"line":{},"character":{}}}}}"#, line, start, line, end);
+        let location = |line, start, end| format!(r#"{{"uri":"{}","range":{}}}"#, URI, range(line, start, end));
+
+        let capabilities = result(&replies, 1).get("capabilities");
+        assert_eq!(capabilities.get("hoverProvider").as_bool(), Some(true));
+        assert_eq!(capabilities.get("textDocumentSync").as_usize(), Some(1));
+
+        let diagnostics = replies.iter().find(|reply| reply.get("method").as_str() == Some("textDocument/publishDiagnostics")).unwrap();
+        assert_eq!(diagnostics.get("params").get("uri").as_str(), Some(URI));
+        let diagnostics = diagnostics.get("params").get("diagnostics").as_array();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].get("range").to_string(), range(2, 6, 7));
+        assert_eq!(diagnostics[0].get("severity").as_usize(), Some(1));
+
+        assert_eq!(result(&replies, 2).to_string(), format!(
+            r#"{{"contents":{{"kind":"plaintext","value":"Int literal"}},"range":{}}}"#, range(0, 13, 15)));
+        assert_eq!(result(&replies, 3).to_string(), location(0, 4, 10));
+        assert_eq!(result(&replies, 4).to_string(), format!("[{},{}]", location(0, 4, 10), location(1, 6, 12)));
+        assert_eq!(result(&replies, 5).to_string(), format!(
+            r#"[{{"name":"answer","kind":13,"range":{},"selectionRange":{}}}]"#, range(0, 0, 16), range(0, 4, 10)));
+
+        // var answer = 42 ; print answer ; print ;
+        let data: Vec<_> = result(&replies, 6).get("data").as_array().iter().map(|n| n.as_usize().unwrap()).collect();
+        assert_eq!(data, [
+            0, 0, 3, 0, 0,
+            0, 4, 6, 4, 0,
+            0, 7, 1, 3, 0,
+            0, 2, 2, 2, 0,
+            1, 0, 5, 0, 0,
+            0, 6, 6, 4, 0,
+            1, 0, 5, 0, 0
+        ]);
+        assert!(result(&replies, 7).is_null());
+        assert_eq!(replies.len(), 8);
+    }
+
+    #[test]
+    fn exit_without_shutdown_fails() {
+        let (code, replies) = session(&[frame(r#"{"jsonrpc":"2.0","method":"exit"}"#)]);
+        assert_eq!(code, 1);
+        assert!(replies.is_empty());
+    }
+}
@@ -1,67 +1,309 @@
+mod check;
+mod cli;
+mod dot;
 mod scanner;
+mod serialize;
+mod sexpr;
 mod grammar;
+mod environment;
+mod interpreter;
 mod token;
 mod parser;
 mod eval;
+mod format;
+mod highlight;
+mod json;
+mod lint;
+mod lsp;
+mod map;
+mod math;
+mod natives;
+mod repl;
+mod syntax;
+mod visit;
 
 extern crate linefeed;
 
-use linefeed::{Interface, ReadResult};
-use eval::Evaluable;
+use cli::{Command, Emit, Source};
+use interpreter::Interpreter;
 use std::env;
+use std::io::Read;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    match args.len() {
-        2 => run_file(args[1].to_string()),
-        1 => run_prompt(),
-        _ => {
-            println!("Usage: rlox [script]");
-            std::process::exit(64);
+    let args: Vec<String> = env::args().skip(1).collect();
+    match cli::parse_args(&args) {
+        Ok(Command::Repl { save_history }) => repl::run_prompt(save_history),
+        Ok(Command::Run { source, args }) => run_script(source, args),
+        Ok(Command::Fmt { files, check, config }) => run_fmt(files, check, config),
+        Ok(Command::Lint { files, config }) => run_lint(files, config),
+        Ok(Command::LintRules) => {
+            for (rule, description) in lint::RULES {
+                println!("{:<20} {}", rule, description);
+            }
+            for (rule, description) in lint::PLANNED_RULES {
+                println!("{:<20} {} (planned)", rule, description);
+            }
         },
+        Ok(Command::Check { files }) => run_check(files),
+        Ok(Command::Parse { file, emit }) => run_parse(file, emit),
+        Ok(Command::RunAst { file }) => run_ast(file),
+        Ok(Command::Lsp) => std::process::exit(lsp::run()),
+        Ok(Command::Help) => print!("{}", cli::USAGE),
+        Ok(Command::Version) => println!("rlox {}", env!("CARGO_PKG_VERSION")),
+        Err(message) => {
+            eprintln!("rlox: {}\n\n{}", message, cli::USAGE);
+            std::process::exit(64);
+        }
     }
 }
 
 // Meta
 
-fn run_prompt() {
-    let reader = Interface::new("rlox").expect("Couldn't initialize prompt reader.");
+fn run_script(source: Source, args: Vec<String>) {
+    let contents = match source {
+        Source::File(file_name) => std::fs::read_to_string(&file_name)
+            .map_err(|e| format!("Couldn't read file '{}': {}", file_name, e)),
+        Source::Inline(code) => Ok(code),
+        Source::Stdin => {
+            let mut code = String::new();
+            std::io::stdin().read_to_string(&mut code)
+                .map(|_| code)
+                .map_err(|e| format!("Couldn't read standard input: {}", e))
+        }
+    };
+    let contents = contents.unwrap_or_else(|message| {
+        eprintln!("rlox: {}", message);
+        std::process::exit(66);
+    });
 
-    reader.set_prompt("rlox> ").expect("Couldn't set reader prompt.");
+    let mut interpreter = Interpreter::new();
+    let args = args.into_iter().map(eval::Value::String).collect();
+    interpreter.define_global("args", eval::Value::list(args));
 
-    while let ReadResult::Input(input) = reader.read_line().expect("Couldn't read line.") {
-        run(input.clone()).err().map(|e| report_error(e));
-        if !input.trim().is_empty() {
-            reader.add_history(input);
+    match run(contents, &mut interpreter) {
+        Ok(Some(val)) => println!("{}", val),
+        Ok(None) => (),
+        Err(errs) => {
+            report_errors(errs);
+            std::process::exit(65);
         }
     }
 }
 
-fn run_file(file_name: String) {
-    let file_contents = std::fs::read_to_string(file_name).expect("Couldn't read file.");
-    run(file_contents).err().map(|e| {
-        report_error(e);
+/// Formats each file in place, or with `check` only reports the files that
+/// would change. `-` formats standard input to standard output.
+fn run_fmt(files: Vec<String>, check: bool, config: format::FormatConfig) {
+    let mut unformatted = false;
+    let mut failed = false;
+    for file in files {
+        let source = if file == "-" {
+            let mut code = String::new();
+            std::io::stdin().read_to_string(&mut code).map(|_| code)
+        } else {
+            std::fs::read_to_string(&file)
+        };
+        let source = match source {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("rlox: Couldn't read file '{}': {}", file, e);
+                failed = true;
+                continue;
+            }
+        };
+
+        let formatted = match format::format_source(&source, &config) {
+            Ok(formatted) => formatted,
+            Err(errs) => {
+                eprintln!("rlox: Couldn't format '{}':", file);
+                report_errors(errs);
+                failed = true;
+                continue;
+            }
+        };
+
+        if check {
+            if formatted != source {
+                println!("{}", file);
+                unformatted = true;
+            }
+        } else if file == "-" {
+            print!("{}", formatted);
+        } else if formatted != source {
+            if let Err(e) = std::fs::write(&file, formatted) {
+                eprintln!("rlox: Couldn't write file '{}': {}", file, e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
         std::process::exit(65);
+    }
+    if unformatted {
+        std::process::exit(1);
+    }
+}
+
+/// Lints each file, printing warnings as `file:line: message [rule]`.
+/// Exits with 1 if there were warnings and 65 if a file couldn't be linted.
+fn run_lint(files: Vec<String>, config_file: Option<String>) {
+    let config_text = match &config_file {
+        Some(file) => std::fs::read_to_string(file).map(Some),
+        None => Ok(std::fs::read_to_string(lint::DEFAULT_CONFIG_FILE).ok())
+    };
+    let config_name = config_file.as_deref().unwrap_or(lint::DEFAULT_CONFIG_FILE);
+    let config_text = config_text.unwrap_or_else(|e| {
+        eprintln!("rlox: Couldn't read lint config '{}': {}", config_name, e);
+        std::process::exit(66);
     });
+    let config = match config_text {
+        Some(text) => lint::LintConfig::parse(&text).unwrap_or_else(|message| {
+            eprintln!("rlox: Invalid lint config '{}': {}", config_name, message);
+            std::process::exit(65);
+        }),
+        None => lint::LintConfig::default()
+    };
+
+    let mut warned = false;
+    let mut failed = false;
+    for file in files {
+        let source = if file == "-" {
+            let mut code = String::new();
+            std::io::stdin().read_to_string(&mut code).map(|_| code)
+        } else {
+            std::fs::read_to_string(&file)
+        };
+        let source = match source {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("rlox: Couldn't read file '{}': {}", file, e);
+                failed = true;
+                continue;
+            }
+        };
+
+        match lint::lint_source(&source, &config) {
+            Ok(warnings) => for warning in warnings {
+                println!("{}:{}: {} [{}]", file, warning.line, warning.message, warning.rule);
+                warned = true;
+            },
+            Err(errs) => {
+                eprintln!("rlox: Couldn't lint '{}':", file);
+                report_errors(errs);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(65);
+    }
+    if warned {
+        std::process::exit(1);
+    }
 }
 
-// Interpretation
+/// Type checks each file. Exits with 65 if any has an error.
+fn run_check(files: Vec<String>) {
+    let mut failed = false;
+    for file in files {
+        let source = if file == "-" {
+            let mut code = String::new();
+            std::io::stdin().read_to_string(&mut code).map(|_| code)
+        } else {
+            std::fs::read_to_string(&file)
+        };
+        let errs = match source {
+            Ok(source) => parse(source).map(|statements| check::check(&statements)).unwrap_or_else(|errs| errs),
+            Err(e) => {
+                eprintln!("rlox: Couldn't read file '{}': {}", file, e);
+                failed = true;
+                continue;
+            }
+        };
+
+        if !errs.is_empty() {
+            eprintln!("rlox: Errors in '{}':", file);
+            report_errors(errs);
+            failed = true;
+        }
+    }
 
-fn run(source_code: String) -> Result<(), RloxError> {
-    let mut scanner = scanner::Scanner::new(source_code);
-    match scanner.scan_tokens() {
-        Ok(tokens) => parser::Parser::new(tokens).parse()
-            .and_then(|expr| expr.evaluate())
-            .map(|val| println!("{}", val)),
+    if failed {
+        std::process::exit(65);
+    }
+}
+
+/// Prints the syntax tree of a file in the `emit` format.
+fn run_parse(file: String, emit: Emit) {
+    let source = read_input(&file);
+    let tokens = scanner::Scanner::new(source).scan_tokens();
+    let parsed = tokens.and_then(|tokens| parser::Parser::new(tokens.clone()).parse().map(|statements| (statements, tokens)));
+    match parsed {
+        Ok((statements, tokens)) => match emit {
+            Emit::Json => println!("{}", serialize::to_json(&statements, &tokens)),
+            Emit::Sexpr => println!("{}", sexpr::to_sexpr(&serialize::to_json(&statements, &tokens))),
+            Emit::Dot => print!("{}", dot::to_dot(&statements))
+        },
         Err(errs) => {
-            for err in errs {
-                report_error(err);
-            }
-            Ok(())
+            report_errors(errs);
+            std::process::exit(65);
+        }
+    }
+}
+
+/// Runs a syntax tree printed by `rlox parse`, in either format.
+fn run_ast(file: String) {
+    let text = read_input(&file);
+    let ast = if text.trim_start().starts_with('(') { sexpr::parse_sexpr(&text) } else { json::Json::parse(&text) };
+    let statements = ast.and_then(|ast| serialize::from_json(&ast)).unwrap_or_else(|message| {
+        eprintln!("rlox: Invalid syntax tree in '{}': {}", file, message);
+        std::process::exit(65);
+    });
+
+    match Interpreter::new().interpret(&statements) {
+        Ok(Some(val)) => println!("{}", val),
+        Ok(None) => (),
+        Err(err) => {
+            report_error(err);
+            std::process::exit(65);
         }
     }
 }
 
+/// Reads a file, or standard input for `-`, exiting if that fails.
+fn read_input(file: &str) -> String {
+    let source = if file == "-" {
+        let mut code = String::new();
+        std::io::stdin().read_to_string(&mut code).map(|_| code)
+    } else {
+        std::fs::read_to_string(file)
+    };
+    source.unwrap_or_else(|e| {
+        eprintln!("rlox: Couldn't read file '{}': {}", file, e);
+        std::process::exit(66);
+    })
+}
+
+// Interpretation
+
+pub fn parse(source_code: String) -> Result<Vec<grammar::Stmt>, Vec<RloxError>> {
+    let tokens = scanner::Scanner::new(source_code).scan_tokens()?;
+    parser::Parser::new(tokens).parse()
+}
+
+/// Runs `source_code` and returns the value of a trailing expression, if any.
+pub fn run(source_code: String, interpreter: &mut Interpreter) -> Result<Option<eval::Value>, Vec<RloxError>> {
+    let statements = parse(source_code)?;
+    interpreter.interpret(&statements).map_err(|e| vec![e])
+}
+
+pub fn report_errors(errs: Vec<RloxError>) {
+    for err in errs {
+        report_error(err);
+    }
+}
+
 fn report_error(err: RloxError) {
     match err.line {
         Some(line) => report(line, err.location, err.message),
@@ -0,0 +1,129 @@
+//! The storage behind `Value::Map`. Entries keep their insertion order so
+//! maps print and iterate the same way every run, and keys are hashed so
+//! that values `==` considers equal, like `1` and `1.0`, find the same entry.
+
+use crate::eval::Value;
+use std::collections::HashMap;
+
+/// A key normalized so equal values hash alike. Integral floats become
+/// integers, so `-0.0`, `0.0` and `0` are all `Int(0)`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum HashKey {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(u64),
+    String(String)
+}
+
+fn hash_key(value: &Value) -> Result<HashKey, String> {
+    match value {
+        Value::Nil => Ok(HashKey::Nil),
+        Value::Boolean(b) => Ok(HashKey::Bool(*b)),
+        Value::Int(n) => Ok(HashKey::Int(*n)),
+        // NaN isn't equal to itself, so it could never be found again.
+        Value::Number(n) if n.is_nan() => Err("NaN can't be a map key.".to_string()),
+        Value::Number(n) if n.fract() == 0.0 && *n >= i64::MIN as f64 && *n < i64::MAX as f64 => {
+            Ok(HashKey::Int(*n as i64))
+        },
+        Value::Number(n) => Ok(HashKey::Float(n.to_bits())),
+        Value::String(string) => Ok(HashKey::String(string.clone())),
+        _ => Err(format!("A {} can't be a map key.", value.type_name()))
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Map {
+    entries: Vec<(Value, Value)>,
+    /// The position of each key's entry in `entries`.
+    index: HashMap<HashKey, usize>
+}
+
+impl Map {
+    /// The entries in insertion order.
+    pub fn entries(&self) -> &[(Value, Value)] {
+        &self.entries
+    }
+
+    pub fn get(&self, key: &Value) -> Result<Option<&Value>, String> {
+        let key = hash_key(key)?;
+        Ok(self.index.get(&key).map(|&i| &self.entries[i].1))
+    }
+
+    /// Sets the value for `key`. A new key goes at the end; an existing one
+    /// keeps its place and the key it was first added with.
+    pub fn insert(&mut self, key: Value, value: Value) -> Result<(), String> {
+        let hashed = hash_key(&key)?;
+        match self.index.get(&hashed) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.index.insert(hashed, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes `key` and returns its value, if it was there.
+    pub fn remove(&mut self, key: &Value) -> Result<Option<Value>, String> {
+        let removed = match self.index.remove(&hash_key(key)?) {
+            Some(i) => i,
+            None => return Ok(None)
+        };
+        for position in self.index.values_mut() {
+            if *position > removed {
+                *position -= 1;
+            }
+        }
+        Ok(Some(self.entries.remove(removed).1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(map: &Map) -> Vec<Value> {
+        map.entries().iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    #[test]
+    fn equal_numbers_are_the_same_key() {
+        let mut map = Map::default();
+        map.insert(Value::Int(1), Value::String("int".to_string())).unwrap();
+        map.insert(Value::Number(1.0), Value::String("float".to_string())).unwrap();
+        map.insert(Value::Number(-0.0), Value::Nil).unwrap();
+        assert_eq!(map.get(&Value::Int(0)), Ok(Some(&Value::Nil)));
+        assert_eq!(map.get(&Value::Number(0.0)), Ok(Some(&Value::Nil)));
+        // The entry keeps the key it was first added with.
+        assert_eq!(keys(&map), vec![Value::Int(1), Value::Number(-0.0)]);
+        assert_eq!(map.get(&Value::Int(1)), Ok(Some(&Value::String("float".to_string()))));
+        assert_eq!(map.get(&Value::Number(1.5)), Ok(None));
+    }
+
+    #[test]
+    fn nan_and_lists_are_not_keys() {
+        let mut map = Map::default();
+        assert_eq!(map.insert(Value::Number(f64::NAN), Value::Nil), Err("NaN can't be a map key.".to_string()));
+        assert_eq!(map.get(&Value::list(vec![])), Err("A List can't be a map key.".to_string()));
+        assert_eq!(map.remove(&Value::list(vec![])), Err("A List can't be a map key.".to_string()));
+        assert!(map.entries().is_empty());
+    }
+
+    #[test]
+    fn removing_keeps_the_order_of_the_rest() {
+        let mut map = Map::default();
+        for n in 0..4 {
+            map.insert(Value::Int(n), Value::Int(n * 10)).unwrap();
+        }
+        assert_eq!(map.remove(&Value::Int(1)), Ok(Some(Value::Int(10))));
+        assert_eq!(map.remove(&Value::Int(1)), Ok(None));
+        assert_eq!(keys(&map), vec![Value::Int(0), Value::Int(2), Value::Int(3)]);
+        // The entries after the removed one are still found where they moved.
+        assert_eq!(map.get(&Value::Int(3)), Ok(Some(&Value::Int(30))));
+        map.insert(Value::Int(1), Value::Nil).unwrap();
+        map.insert(Value::Int(2), Value::Int(-2)).unwrap();
+        assert_eq!(keys(&map), vec![Value::Int(0), Value::Int(2), Value::Int(3), Value::Int(1)]);
+        assert_eq!(map.get(&Value::Int(2)), Ok(Some(&Value::Int(-2))));
+    }
+}
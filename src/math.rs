@@ -0,0 +1,594 @@
+//! The floating point functions behind the `math` module. They are
+//! written here with only `+ - * /` and `sqrt`, which IEEE 754 rounds the
+//! same way everywhere, instead of calling the platform's C library, whose
+//! `sin` or `pow` can differ in the last bit from one system to the next.
+//! The algorithms and coefficients follow fdlibm and are accurate to within
+//! an ulp or two.
+
+// The constants are copied digit for digit from fdlibm, so they can be
+// checked against it.
+#![allow(clippy::excessive_precision)]
+
+const LN2_HI: f64 = 6.93147180369123816490e-01;
+const LN2_LO: f64 = 1.90821492927058770002e-10;
+const PI_LO: f64 = 1.2246467991473531772e-16;
+
+/// `x * 2^k`, without overflowing on the way to a result in range.
+fn scale(mut x: f64, mut k: i32) -> f64 {
+    while k > 1023 {
+        x *= f64::from_bits(0x7fe << 52);
+        k -= 1023;
+    }
+    while k < -1022 {
+        x *= f64::from_bits(1 << 52);
+        k += 1022;
+    }
+    x * f64::from_bits(((1023 + k) as u64) << 52)
+}
+
+pub fn exp(x: f64) -> f64 {
+    const P1: f64 = 1.66666666666666019037e-01;
+    const P2: f64 = -2.77777777770155933842e-03;
+    const P3: f64 = 6.61375632143793436117e-05;
+    const P4: f64 = -1.65339022054652515390e-06;
+    const P5: f64 = 4.13813679705723846039e-08;
+
+    if x.is_nan() {
+        return x;
+    }
+    if x > 709.782712893383973096 {
+        return f64::INFINITY;
+    }
+    if x < -745.13321910194110842 {
+        return 0.0;
+    }
+    if x.abs() < f64::from_bits(0x3e30_0000_0000_0000) {
+        return 1.0 + x;
+    }
+
+    // x = k ln 2 + r, with |r| <= ln 2 / 2.
+    let k = (std::f64::consts::LOG2_E * x + 0.5f64.copysign(x)) as i32;
+    let hi = x - f64::from(k) * LN2_HI;
+    let lo = f64::from(k) * LN2_LO;
+    let r = hi - lo;
+    let rr = r * r;
+    let c = r - rr * (P1 + rr * (P2 + rr * (P3 + rr * (P4 + rr * P5))));
+    let y = 1.0 + (r * c / (2.0 - c) - lo + hi);
+    scale(y, k)
+}
+
+/// Splits a positive finite `x` into `k` and `f` with `x = 2^k (1 + f)` and
+/// `1 + f` between `sqrt(2) / 2` and `sqrt(2)`.
+fn reduce_log(x: f64) -> (i32, f64) {
+    let (mut bits, mut k) = (x.to_bits(), 0);
+    if bits < 1 << 52 {
+        // Subnormal: scale it up into the normal range first.
+        bits = (x * f64::from_bits(0x4350_0000_0000_0000)).to_bits();
+        k -= 54;
+    }
+    let high = (bits >> 32) as u32 + (0x3ff0_0000 - 0x3fe6_a09e);
+    k += (high >> 20) as i32 - 0x3ff;
+    let high = (high & 0x000f_ffff) + 0x3fe6_a09e;
+    let m = f64::from_bits(u64::from(high) << 32 | (bits & 0xffff_ffff));
+    (k, m - 1.0)
+}
+
+/// `log(1 + f) - f + f * f / 2` for the `f` of `reduce_log`.
+fn log_tail(f: f64) -> (f64, f64) {
+    const LG1: f64 = 6.666666666666735130e-01;
+    const LG2: f64 = 3.999999999940941908e-01;
+    const LG3: f64 = 2.857142874366239149e-01;
+    const LG4: f64 = 2.222219843214978396e-01;
+    const LG5: f64 = 1.818357216161805012e-01;
+    const LG6: f64 = 1.531383769920937332e-01;
+    const LG7: f64 = 1.479819860511658591e-01;
+
+    let s = f / (2.0 + f);
+    let z = s * s;
+    let w = z * z;
+    let r = z * (LG1 + w * (LG3 + w * (LG5 + w * LG7))) + w * (LG2 + w * (LG4 + w * LG6));
+    let hfsq = 0.5 * f * f;
+    (s * (hfsq + r), hfsq)
+}
+
+/// The natural logarithm.
+pub fn ln(x: f64) -> f64 {
+    if x.is_nan() || x < 0.0 {
+        return f64::NAN;
+    }
+    if x == 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if x.is_infinite() {
+        return x;
+    }
+    let (k, f) = reduce_log(x);
+    let (tail, hfsq) = log_tail(f);
+    let k = f64::from(k);
+    tail + k * LN2_LO - hfsq + f + k * LN2_HI
+}
+
+pub fn log10(x: f64) -> f64 {
+    let y = ln(x) / std::f64::consts::LN_10;
+    // Exact powers of ten should give exact answers.
+    let n = y.round();
+    if (y - n).abs() < 1e-9 && n.abs() <= 22.0 {
+        let power = (0..n.abs() as i32).fold(1.0, |power, _| power * 10.0);
+        if x == power || x == 1.0 / power {
+            return n;
+        }
+    }
+    y
+}
+
+pub fn log2(x: f64) -> f64 {
+    // Exact powers of two should give exact answers.
+    if x.is_normal() && x > 0.0 && x.to_bits().trailing_zeros() >= 52 {
+        return f64::from((x.to_bits() >> 52) as i32 - 1023);
+    }
+    ln(x) / std::f64::consts::LN_2
+}
+
+/// Reduces a finite `x` to `r` within π/4 of 0 and the number of quarter
+/// turns, modulo 4, taken off.
+fn reduce_trig(x: f64) -> (f64, u8) {
+    const PIO2_1: f64 = 1.57079632673412561417e+00;
+    const PIO2_2: f64 = 6.07710050630396597660e-11;
+    const PIO2_3: f64 = 2.02226624871116645580e-21;
+    const PIO2_3T: f64 = 8.47842766036889956997e-32;
+
+    if x.abs() <= std::f64::consts::FRAC_PI_4 {
+        return (x, 0);
+    }
+    // Each part of π/2 has few enough bits that `n` times it is exact
+    // while `n` is below 2^20.
+    if x.abs() < 1647099.0 {
+        let n = (x * std::f64::consts::FRAC_2_PI).round();
+        let r = x - n * PIO2_1 - n * PIO2_2 - n * PIO2_3 - n * PIO2_3T;
+        return (r, (n % 4.0 + 4.0) as u8 % 4);
+    }
+
+    let (r, n) = reduce_trig_large(x.abs());
+    if x < 0.0 { (-r, (4 - n) % 4) } else { (r, n) }
+}
+
+/// The bits of 2/π, most significant first, enough for the largest `f64`.
+const TWO_OVER_PI: [u64; 24] = [
+    0xa2f9836e4e441529, 0xfc2757d1f534ddc0, 0xdb6295993c439041,
+    0xfe5163abdebbc561, 0xb7246e3a424dd2e0, 0x06492eea09d1921c,
+    0xfe1deb1cb129a73e, 0xe88235f52ebb4484, 0xe99c7026b45f7e41,
+    0x3991d639835339f4, 0x9c845f8bbdf9283b, 0x1ff897ffde05980f,
+    0xef2f118b5a0a6d1f, 0x6d367ecf27cb09b7, 0x4f463f669e5fea2d,
+    0x7527bac7ebe5f17b, 0x3d0739f78a5292ea, 0x6bfb5fb11f8d5d08,
+    0x56033046fc7b6bab, 0xf0cfbc209af4361d, 0xa9e391615ee61b08,
+    0x6599855f14a06840, 0x8dffd8804d732731, 0x06061556ca73a8c9
+];
+
+/// The 64 bits of 2/π starting `start` bits after the binary point. Bits
+/// before the point are all zero.
+fn two_over_pi_bits(start: i64) -> u64 {
+    if start <= -64 {
+        return 0;
+    }
+    if start < 0 {
+        return TWO_OVER_PI[0] >> -start;
+    }
+    let (word, shift) = ((start / 64) as usize, start % 64);
+    match shift {
+        0 => TWO_OVER_PI[word],
+        _ => TWO_OVER_PI[word] << shift | TWO_OVER_PI[word + 1] >> (64 - shift)
+    }
+}
+
+/// Payne–Hanek reduction of a positive `x` too large for `reduce_trig` to
+/// take multiples of π/2 off directly. With `x = m * 2^e`, only the bits of
+/// 2/π from about `e` on matter to the quarter turns modulo 4 and the
+/// fraction left over, so `m` is multiplied by a 192 bit window of them.
+fn reduce_trig_large(x: f64) -> (f64, u8) {
+    const PIO2_LO: f64 = 6.12323399573676603587e-17;
+
+    let bits = x.to_bits();
+    let e = (bits >> 52) as i64 - 1075;
+    let m = u128::from(bits & ((1 << 52) - 1) | 1 << 52);
+
+    // Bits of 2/π before the window only add multiples of 4 to `x * 2/π`.
+    // Starting it at bit `e - 1` leaves the point 190 bits up the product.
+    let start = e - 2;
+    let low = m * u128::from(two_over_pi_bits(start + 128));
+    let middle = m * u128::from(two_over_pi_bits(start + 64)) + (low >> 64);
+    let high = m * u128::from(two_over_pi_bits(start)) + (middle >> 64);
+
+    // The 128 bits of the fraction below the point, rounded to the
+    // nearest quarter turn.
+    let mut n = (high >> 62) as u8 & 3;
+    let mut fraction = high << 66 | (middle as u64 as u128) << 2 | (low as u64 >> 62) as u128;
+    let negative = fraction >> 127 == 1;
+    if negative {
+        n = (n + 1) & 3;
+        fraction = fraction.wrapping_neg();
+    }
+    if fraction == 0 {
+        return (0.0, n);
+    }
+
+    let zeros = fraction.leading_zeros() as i32;
+    let top = (fraction << zeros) >> 22;
+    let hi = top as f64;
+    let lo = (top as i128 - hi as i128) as f64;
+    let unit = scale(1.0, -(106 + zeros));
+    let (r, _) = mul_dd((hi * unit, lo * unit), (std::f64::consts::FRAC_PI_2, PIO2_LO));
+    (if negative { -r } else { r }, n)
+}
+
+fn kernel_sin(x: f64) -> f64 {
+    const S1: f64 = -1.66666666666666324348e-01;
+    const S2: f64 = 8.33333333332248946124e-03;
+    const S3: f64 = -1.98412698298579493134e-04;
+    const S4: f64 = 2.75573137070700676789e-06;
+    const S5: f64 = -2.50507602534068634195e-08;
+    const S6: f64 = 1.58969099521155010221e-10;
+
+    let z = x * x;
+    let r = S2 + z * (S3 + z * (S4 + z * (S5 + z * S6)));
+    x + z * x * (S1 + z * r)
+}
+
+fn kernel_cos(x: f64) -> f64 {
+    const C1: f64 = 4.16666666666666019037e-02;
+    const C2: f64 = -1.38888888888741095749e-03;
+    const C3: f64 = 2.48015872894767294178e-05;
+    const C4: f64 = -2.75573143513906633035e-07;
+    const C5: f64 = 2.08757232129817482790e-09;
+    const C6: f64 = -1.13596475577881948265e-11;
+
+    let z = x * x;
+    let r = z * (C1 + z * (C2 + z * (C3 + z * (C4 + z * (C5 + z * C6)))));
+    let hz = 0.5 * z;
+    let w = 1.0 - hz;
+    w + (((1.0 - w) - hz) + z * r)
+}
+
+pub fn sin(x: f64) -> f64 {
+    if !x.is_finite() {
+        return f64::NAN;
+    }
+    match reduce_trig(x) {
+        (r, 0) => kernel_sin(r),
+        (r, 1) => kernel_cos(r),
+        (r, 2) => -kernel_sin(r),
+        (r, _) => -kernel_cos(r)
+    }
+}
+
+pub fn cos(x: f64) -> f64 {
+    if !x.is_finite() {
+        return f64::NAN;
+    }
+    match reduce_trig(x) {
+        (r, 0) => kernel_cos(r),
+        (r, 1) => -kernel_sin(r),
+        (r, 2) => -kernel_cos(r),
+        (r, _) => kernel_sin(r)
+    }
+}
+
+pub fn tan(x: f64) -> f64 {
+    if !x.is_finite() {
+        return f64::NAN;
+    }
+    match reduce_trig(x) {
+        (r, n) if n % 2 == 0 => kernel_sin(r) / kernel_cos(r),
+        (r, _) => -kernel_cos(r) / kernel_sin(r)
+    }
+}
+
+pub fn atan(x: f64) -> f64 {
+    const ATAN_HI: [f64; 4] = [
+        4.63647609000806093515e-01,
+        std::f64::consts::FRAC_PI_4,
+        9.82793723247329054082e-01,
+        std::f64::consts::FRAC_PI_2
+    ];
+    const ATAN_LO: [f64; 4] = [
+        2.26987774529616870924e-17,
+        3.06161699786838301793e-17,
+        1.39033110312309984516e-17,
+        6.12323399573676603587e-17
+    ];
+    const AT: [f64; 11] = [
+        3.33333333333329318027e-01,
+        -1.99999999998764832476e-01,
+        1.42857142725034663711e-01,
+        -1.11111104054623557880e-01,
+        9.09088713343650656196e-02,
+        -7.69187620504482999495e-02,
+        6.66107313738753120669e-02,
+        -5.83357013379057348645e-02,
+        4.97687799461593236017e-02,
+        -3.65315727442169155270e-02,
+        1.62858201153657823623e-02
+    ];
+
+    if x.is_nan() {
+        return x;
+    }
+    let ax = x.abs();
+    if ax >= f64::from_bits(0x4410_0000_0000_0000) {
+        return (ATAN_HI[3] + ATAN_LO[3]).copysign(x);
+    }
+    if ax < f64::from_bits(0x3e40_0000_0000_0000) {
+        return x;
+    }
+
+    // Move the argument next to one of the points in ATAN_HI.
+    let (id, t) = if ax < 0.4375 {
+        (None, x)
+    } else if ax < 0.6875 {
+        (Some(0), (2.0 * ax - 1.0) / (2.0 + ax))
+    } else if ax < 1.1875 {
+        (Some(1), (ax - 1.0) / (ax + 1.0))
+    } else if ax < 2.4375 {
+        (Some(2), (ax - 1.5) / (1.0 + 1.5 * ax))
+    } else {
+        (Some(3), -1.0 / ax)
+    };
+
+    let z = t * t;
+    let w = z * z;
+    let s1 = z * (AT[0] + w * (AT[2] + w * (AT[4] + w * (AT[6] + w * (AT[8] + w * AT[10])))));
+    let s2 = w * (AT[1] + w * (AT[3] + w * (AT[5] + w * (AT[7] + w * AT[9]))));
+    match id {
+        None => t - t * (s1 + s2),
+        Some(id) => (ATAN_HI[id] - ((t * (s1 + s2) - ATAN_LO[id]) - t)).copysign(x)
+    }
+}
+
+/// The angle from the positive x axis to the point (`x`, `y`).
+pub fn atan2(y: f64, x: f64) -> f64 {
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    if x.is_nan() || y.is_nan() {
+        return f64::NAN;
+    }
+    if x == 1.0 {
+        return atan(y);
+    }
+    if y == 0.0 {
+        return if x.is_sign_positive() { y } else { PI.copysign(y) };
+    }
+    if x == 0.0 {
+        return FRAC_PI_2.copysign(y);
+    }
+    if x.is_infinite() {
+        let angle = match (x > 0.0, y.is_infinite()) {
+            (true, true) => FRAC_PI_4,
+            (false, true) => 3.0 * FRAC_PI_4,
+            (true, false) => 0.0,
+            (false, false) => PI
+        };
+        return angle.copysign(y);
+    }
+    if y.is_infinite() {
+        return FRAC_PI_2.copysign(y);
+    }
+
+    let z = atan((y / x).abs());
+    if x > 0.0 {
+        z.copysign(y)
+    } else {
+        (PI - (z - PI_LO)).copysign(y)
+    }
+}
+
+pub fn asin(x: f64) -> f64 {
+    if x.abs() > 1.0 {
+        return f64::NAN;
+    }
+    atan2(x, ((1.0 - x) * (1.0 + x)).sqrt())
+}
+
+pub fn acos(x: f64) -> f64 {
+    if x.abs() > 1.0 {
+        return f64::NAN;
+    }
+    atan2(((1.0 - x) * (1.0 + x)).sqrt(), x)
+}
+
+// Double-double arithmetic: a value kept as an unevaluated sum `hi + lo`
+// for about twice the precision, which `pow` needs to stay within an ulp.
+
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+fn split(a: f64) -> (f64, f64) {
+    let c = 134217729.0 * a;
+    let hi = c - (c - a);
+    (hi, a - hi)
+}
+
+/// The exact product of `a` and `b` as `hi + lo`. Both must be below 2^995.
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    let (ah, al) = split(a);
+    let (bh, bl) = split(b);
+    (p, ((ah * bh - p) + ah * bl + al * bh) + al * bl)
+}
+
+fn mul_dd((ah, al): (f64, f64), (bh, bl): (f64, f64)) -> (f64, f64) {
+    let (p, e) = two_prod(ah, bh);
+    two_sum(p, e + (ah * bl + al * bh))
+}
+
+/// The natural logarithm of a positive finite `x` as `hi + lo`.
+fn ln_dd(x: f64) -> (f64, f64) {
+    let (k, f) = reduce_log(x);
+    let (tail, _) = log_tail(f);
+    let (fsq, fsq_lo) = two_prod(f, f);
+    let k = f64::from(k);
+    let (h1, e1) = two_sum(k * LN2_HI, f);
+    let (h2, e2) = two_sum(h1, -0.5 * fsq);
+    two_sum(h2, e1 + e2 + (tail - 0.5 * fsq_lo + k * LN2_LO))
+}
+
+/// `x` to the power `n` by repeated squaring, or `None` if it gets too big
+/// or too small to multiply exactly.
+fn powi_dd(x: f64, mut n: u32) -> Option<(f64, f64)> {
+    let (min, max) = (f64::from_bits(0x0360_0000_0000_0000), f64::from_bits(0x7e20_0000_0000_0000));
+    let (mut result, mut base): ((f64, f64), (f64, f64)) = ((1.0, 0.0), (x, 0.0));
+    while n > 0 {
+        if !(min..=max).contains(&result.0.abs()) || !(min..=max).contains(&base.0.abs()) {
+            return None;
+        }
+        if n & 1 == 1 {
+            result = mul_dd(result, base);
+        }
+        n >>= 1;
+        if n > 0 {
+            base = mul_dd(base, base);
+        }
+    }
+    Some(result).filter(|(hi, _)| hi.is_finite() && hi.abs() >= min)
+}
+
+/// `x` raised to `y`, with the special cases of C's `pow`.
+pub fn pow(x: f64, y: f64) -> f64 {
+    if y == 0.0 || x == 1.0 {
+        return 1.0;
+    }
+    if x.is_nan() || y.is_nan() {
+        return f64::NAN;
+    }
+    if y.is_infinite() {
+        return match (x.abs() == 1.0, x.abs() < 1.0, y > 0.0) {
+            (true, _, _) => 1.0,
+            (false, true, true) | (false, false, false) => 0.0,
+            _ => f64::INFINITY
+        };
+    }
+
+    let is_integer = y.fract() == 0.0;
+    if x < 0.0 && !is_integer {
+        return f64::NAN;
+    }
+    let odd = is_integer && y.abs() < 9007199254740992.0 && (y % 2.0).abs() == 1.0;
+    let sign = if x.is_sign_negative() && odd { -1.0 } else { 1.0 };
+    let ax = x.abs();
+    if ax == 0.0 || ax.is_infinite() {
+        let grows = (ax == 0.0) == (y < 0.0);
+        return sign * if grows { f64::INFINITY } else { 0.0 };
+    }
+
+    if is_integer && y.abs() <= 1024.0 {
+        if let Some((hi, lo)) = powi_dd(ax, y.abs() as u32) {
+            let result = if y > 0.0 {
+                hi + lo
+            } else {
+                // 1 / (hi + lo), with one correction step.
+                let q = 1.0 / hi;
+                let (p, e) = two_prod(q, hi);
+                q + q * ((1.0 - p) - e - q * lo)
+            };
+            return sign * result;
+        }
+    }
+
+    let (hi, lo) = ln_dd(ax);
+    let p = y * hi;
+    if p > 710.0 {
+        return sign * f64::INFINITY;
+    }
+    if p < -746.0 {
+        return sign * 0.0;
+    }
+    let (p, e) = two_prod(y, hi);
+    let e = e + y * lo;
+    let result = exp(p);
+    sign * (result + result * e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_4, FRAC_PI_6, PI};
+
+    /// How many representable floats apart `a` and `b` are.
+    fn ulps(a: f64, b: f64) -> u64 {
+        let ordered = |x: f64| if x.is_sign_negative() { -(x.abs().to_bits() as i64) } else { x.to_bits() as i64 };
+        ordered(a).abs_diff(ordered(b))
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(ulps(actual, expected) <= 1, "got {:?}, expected {:?}", actual, expected);
+    }
+
+    #[test]
+    fn trig_functions_match_known_values() {
+        // x, sin x, cos x and tan x, correctly rounded.
+        let cases = [
+            (0.0, 0.0, 1.0, 0.0),
+            (FRAC_PI_4, 0.7071067811865475, FRAC_1_SQRT_2, 0.9999999999999999),
+            (1e6, -0.34999350217129294, 0.9367521275331447, -0.373624453987599),
+            (1647099.0, -0.3232532403530771, 0.9463124973290988, -0.34159248796294767),
+            (1e16, 0.7796880066069788, -0.6261681981330862, -1.2451734357184063),
+            (1e22, -0.8522008497671888, 0.523214785395139, -1.6287782256068988),
+            (-1e22, 0.8522008497671888, 0.523214785395139, 1.6287782256068988),
+            (1e300, -0.8178819121159085, -0.5753861119575491, 1.4214488238747245),
+            (f64::MAX, 0.004961954789184062, -0.9999876894265599, -0.004962015874444895),
+            // The double closest to a multiple of π/2, where the reduced
+            // argument is 4.687165924254628e-19 worked out exactly.
+            (6381956970095103.0 * scale(1.0, 797), 1.0, -4.687165924254628e-19, -2.133485385753704e+18)
+        ];
+        for (x, sin_x, cos_x, tan_x) in cases {
+            assert_close(sin(x), sin_x);
+            assert_close(cos(x), cos_x);
+            assert_close(tan(x), tan_x);
+        }
+    }
+
+    #[test]
+    fn trig_functions_of_infinities_and_nan_are_nan() {
+        for x in [f64::INFINITY, f64::NEG_INFINITY, f64::NAN] {
+            assert!(sin(x).is_nan() && cos(x).is_nan() && tan(x).is_nan());
+        }
+    }
+
+    #[test]
+    fn inverse_trig_functions_match_known_values() {
+        assert_close(atan(1.0), FRAC_PI_4);
+        assert_close(atan2(0.0, -1.0), PI);
+        assert_close(atan2(-1.0, -1.0), -3.0 * FRAC_PI_4);
+        assert_close(asin(0.5), FRAC_PI_6);
+        assert_close(acos(-1.0), PI);
+        assert!(asin(2.0).is_nan());
+    }
+
+    #[test]
+    fn exponentials_and_logarithms_match_known_values() {
+        assert_eq!(exp(0.0), 1.0);
+        assert_close(exp(1.0), std::f64::consts::E);
+        assert_close(exp(-745.0), 5e-324);
+        assert_eq!(exp(710.0), f64::INFINITY);
+        assert_eq!(ln(1.0), 0.0);
+        assert_close(ln(10.0), std::f64::consts::LN_10);
+        assert_eq!(ln(0.0), f64::NEG_INFINITY);
+        assert!(ln(-1.0).is_nan());
+        assert_eq!(log10(1000.0), 3.0);
+        assert_eq!(log2(1024.0), 10.0);
+        assert_close(log2(3.0), 1.584962500721156);
+    }
+
+    #[test]
+    fn pow_matches_known_values() {
+        assert_eq!(pow(2.0, 10.0), 1024.0);
+        assert_eq!(pow(2.0, -1.0), 0.5);
+        assert_close(pow(2.0, 0.5), std::f64::consts::SQRT_2);
+        assert_close(pow(10.0, 0.3), 1.9952623149688795);
+        assert_eq!(pow(-2.0, 3.0), -8.0);
+        assert!(pow(-2.0, 0.5).is_nan());
+        assert_eq!(pow(0.0, -1.0), f64::INFINITY);
+        assert_eq!(pow(f64::NAN, 0.0), 1.0);
+    }
+}
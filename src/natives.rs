@@ -0,0 +1,608 @@
+//! Functions built into the interpreter. Each is defined as a global when
+//! an `Interpreter` starts, so scripts call them like any other value, and
+//! they report bad arguments at the call's closing `)`. Modules like `math`
+//! are globals too, and group more natives under `module.name`.
+
+use crate::eval::{sequence_index, runtime_err, Value};
+use crate::map::Map;
+use crate::math;
+use crate::scanner;
+use crate::token::{Literal, Token};
+use crate::RloxError;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+/// The longest string `repeat()` will build, in bytes.
+const MAX_STRING_LEN: usize = 1 << 30;
+
+pub struct NativeFunction {
+    pub name: &'static str,
+    /// How many arguments the function takes.
+    arity: RangeInclusive<usize>,
+    function: fn(&Token, Vec<Value>) -> Result<Value, RloxError>
+}
+
+pub static NATIVES: &[NativeFunction] = &[
+    NativeFunction { name: "len", arity: 1..=1, function: len },
+    NativeFunction { name: "push", arity: 2..=2, function: push },
+    NativeFunction { name: "pop", arity: 1..=1, function: pop },
+    NativeFunction { name: "insert", arity: 3..=3, function: insert },
+    NativeFunction { name: "remove", arity: 2..=2, function: remove },
+    NativeFunction { name: "slice", arity: 2..=3, function: slice },
+    NativeFunction { name: "contains", arity: 2..=2, function: contains },
+    NativeFunction { name: "keys", arity: 1..=1, function: keys },
+    NativeFunction { name: "values", arity: 1..=1, function: values },
+    NativeFunction { name: "has", arity: 2..=2, function: has },
+    NativeFunction { name: "delete", arity: 2..=2, function: delete },
+    NativeFunction { name: "upper", arity: 1..=1, function: upper },
+    NativeFunction { name: "lower", arity: 1..=1, function: lower },
+    NativeFunction { name: "trim", arity: 1..=1, function: trim },
+    NativeFunction { name: "split", arity: 2..=2, function: split },
+    NativeFunction { name: "join", arity: 2..=2, function: join },
+    NativeFunction { name: "replace", arity: 3..=3, function: replace },
+    NativeFunction { name: "find", arity: 2..=2, function: find },
+    NativeFunction { name: "starts_with", arity: 2..=2, function: starts_with },
+    NativeFunction { name: "ends_with", arity: 2..=2, function: ends_with },
+    NativeFunction { name: "repeat", arity: 2..=2, function: repeat },
+    NativeFunction { name: "chars", arity: 1..=1, function: chars },
+    NativeFunction { name: "str", arity: 1..=1, function: |_, arguments| Ok(Value::String(arguments[0].to_string())) },
+    NativeFunction { name: "num", arity: 1..=1, function: num },
+    NativeFunction { name: "bool", arity: 1..=1, function: |_, arguments| Ok(Value::Boolean(arguments[0].is_truthy())) },
+    NativeFunction { name: "type", arity: 1..=1, function: |_, arguments| Ok(Value::String(arguments[0].type_name().to_string())) },
+    NativeFunction { name: "repr", arity: 1..=1, function: |_, arguments| Ok(Value::String(arguments[0].repr())) }
+];
+
+/// A namespace of natives and constants, whose members are read with
+/// `module.name`.
+pub struct Module {
+    pub name: &'static str,
+    constants: &'static [(&'static str, f64)],
+    /// Named `module.member`, so errors say which module they came from.
+    functions: &'static [NativeFunction]
+}
+
+pub static MODULES: &[Module] = &[
+    Module {
+        name: "math",
+        constants: &[
+            ("pi", std::f64::consts::PI),
+            ("e", std::f64::consts::E),
+            ("inf", f64::INFINITY),
+            ("nan", f64::NAN)
+        ],
+        functions: &[
+            NativeFunction { name: "math.sqrt", arity: 1..=1, function: |paren, arguments| float(paren, "math.sqrt", &arguments, f64::sqrt) },
+            NativeFunction { name: "math.pow", arity: 2..=2, function: math_pow },
+            NativeFunction { name: "math.abs", arity: 1..=1, function: math_abs },
+            NativeFunction { name: "math.floor", arity: 1..=1, function: |paren, arguments| integral(paren, "math.floor", &arguments, f64::floor) },
+            NativeFunction { name: "math.ceil", arity: 1..=1, function: |paren, arguments| integral(paren, "math.ceil", &arguments, f64::ceil) },
+            NativeFunction { name: "math.round", arity: 1..=1, function: |paren, arguments| integral(paren, "math.round", &arguments, f64::round) },
+            NativeFunction { name: "math.min", arity: 1..=usize::MAX, function: |paren, arguments| extreme(paren, "math.min", arguments, Ordering::Less) },
+            NativeFunction { name: "math.max", arity: 1..=usize::MAX, function: |paren, arguments| extreme(paren, "math.max", arguments, Ordering::Greater) },
+            NativeFunction { name: "math.sin", arity: 1..=1, function: |paren, arguments| float(paren, "math.sin", &arguments, math::sin) },
+            NativeFunction { name: "math.cos", arity: 1..=1, function: |paren, arguments| float(paren, "math.cos", &arguments, math::cos) },
+            NativeFunction { name: "math.tan", arity: 1..=1, function: |paren, arguments| float(paren, "math.tan", &arguments, math::tan) },
+            NativeFunction { name: "math.asin", arity: 1..=1, function: |paren, arguments| float(paren, "math.asin", &arguments, math::asin) },
+            NativeFunction { name: "math.acos", arity: 1..=1, function: |paren, arguments| float(paren, "math.acos", &arguments, math::acos) },
+            NativeFunction { name: "math.atan", arity: 1..=1, function: |paren, arguments| float(paren, "math.atan", &arguments, math::atan) },
+            NativeFunction { name: "math.atan2", arity: 2..=2, function: math_atan2 },
+            NativeFunction { name: "math.exp", arity: 1..=1, function: |paren, arguments| float(paren, "math.exp", &arguments, math::exp) },
+            NativeFunction { name: "math.log", arity: 1..=1, function: |paren, arguments| float(paren, "math.log", &arguments, math::ln) },
+            NativeFunction { name: "math.log10", arity: 1..=1, function: |paren, arguments| float(paren, "math.log10", &arguments, math::log10) },
+            NativeFunction { name: "math.log2", arity: 1..=1, function: |paren, arguments| float(paren, "math.log2", &arguments, math::log2) },
+            NativeFunction { name: "math.is_nan", arity: 1..=1, function: math_is_nan },
+            NativeFunction { name: "math.is_finite", arity: 1..=1, function: math_is_finite }
+        ]
+    }
+];
+
+impl Module {
+    /// The constant or function called `name`, if the module has one.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        if let Some((_, constant)) = self.constants.iter().find(|(constant, _)| *constant == name) {
+            return Some(Value::Number(*constant));
+        }
+        self.functions.iter().find(|function| self.member_name(function) == Some(name)).map(Value::Native)
+    }
+
+    pub fn member_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        let functions = self.functions.iter().filter_map(move |function| self.member_name(function));
+        self.constants.iter().map(|(name, _)| *name).chain(functions)
+    }
+
+    /// A function's name without the `module.` in front.
+    fn member_name(&self, function: &'static NativeFunction) -> Option<&'static str> {
+        function.name.strip_prefix(self.name).and_then(|rest| rest.strip_prefix('.'))
+    }
+}
+
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<module {}>", self.name)
+    }
+}
+
+/// Modules are only ever defined once, so their names tell them apart.
+impl PartialEq for Module {
+    fn eq(&self, other: &Module) -> bool {
+        self.name == other.name
+    }
+}
+
+impl NativeFunction {
+    pub fn call(&self, paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+        if !self.arity.contains(&arguments.len()) {
+            let expected = match (self.arity.start(), self.arity.end()) {
+                (1, 1) => "1 argument".to_string(),
+                (1, &usize::MAX) => "at least 1 argument".to_string(),
+                (min, max) if min == max => format!("{} arguments", min),
+                (min, max) => format!("{} to {} arguments", min, max)
+            };
+            let message = format!("{}() takes {} but got {}.", self.name, expected, arguments.len());
+            return Err(runtime_err(paren, &message));
+        }
+        (self.function)(paren, arguments)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+/// Natives are only ever defined once, so their names tell them apart.
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &NativeFunction) -> bool {
+        self.name == other.name
+    }
+}
+
+fn len(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let length = match &arguments[0] {
+        Value::List(values) => values.borrow().len(),
+        Value::Map(map) => map.borrow().entries().len(),
+        Value::String(string) => string.chars().count(),
+        value => {
+            let message = format!("len() expects a list, map or string, not {}.", value.type_name());
+            return Err(runtime_err(paren, &message));
+        }
+    };
+    Ok(Value::Int(i64::try_from(length).unwrap_or(i64::MAX)))
+}
+
+fn push(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let list = list_arg(paren, "push", &arguments[0])?;
+    list.borrow_mut().push(arguments[1].clone());
+    Ok(Value::Nil)
+}
+
+fn pop(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let list = list_arg(paren, "pop", &arguments[0])?;
+    let popped = list.borrow_mut().pop();
+    popped.ok_or_else(|| runtime_err(paren, "Can't pop from an empty list."))
+}
+
+/// Inserts before the element at the index. An index equal to the length
+/// appends.
+fn insert(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let list = list_arg(paren, "insert", &arguments[0])?;
+    let mut values = list.borrow_mut();
+    let index = match arguments[1] {
+        Value::Int(n) if usize::try_from(n) == Ok(values.len()) => values.len(),
+        _ => sequence_index(paren, "List", &arguments[1], values.len())?
+    };
+    values.insert(index, arguments[2].clone());
+    Ok(Value::Nil)
+}
+
+/// Removes the element at the index and returns it.
+fn remove(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let list = list_arg(paren, "remove", &arguments[0])?;
+    let mut values = list.borrow_mut();
+    let index = sequence_index(paren, "List", &arguments[1], values.len())?;
+    Ok(values.remove(index))
+}
+
+/// A new list of the elements, or a new string of the characters, from
+/// `start` up to but not including `end`, which defaults to the length.
+fn slice(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let (kind, len) = match &arguments[0] {
+        Value::List(values) => ("list", values.borrow().len()),
+        Value::String(string) => ("string", string.chars().count()),
+        value => {
+            let message = format!("slice() expects a list or string, not {}.", value.type_name());
+            return Err(runtime_err(paren, &message));
+        }
+    };
+    let start = int_arg(paren, "slice", &arguments[1])?;
+    let end = match arguments.get(2) {
+        Some(end) => int_arg(paren, "slice", end)?,
+        None => i64::try_from(len).unwrap_or(i64::MAX)
+    };
+
+    let range = usize::try_from(start).ok()
+        .zip(usize::try_from(end).ok())
+        .filter(|(start, end)| start <= end && *end <= len);
+    match (range, &arguments[0]) {
+        (Some((start, end)), Value::List(values)) => Ok(Value::list(values.borrow()[start..end].to_vec())),
+        (Some((start, end)), Value::String(string)) => Ok(Value::String(string.chars().skip(start).take(end - start).collect())),
+        _ => {
+            let message = format!("Slice {}..{} is out of bounds for a {} of length {}.", start, end, kind, len);
+            Err(runtime_err(paren, &message))
+        }
+    }
+}
+
+fn contains(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let values = list_arg(paren, "contains", &arguments[0])?.borrow();
+    Ok(Value::Boolean(values.iter().any(|value| value.is_equal(&arguments[1]))))
+}
+
+/// The keys of a map as a list, in insertion order.
+fn keys(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let map = map_arg(paren, "keys", &arguments[0])?.borrow();
+    Ok(Value::list(map.entries().iter().map(|(key, _)| key.clone()).collect()))
+}
+
+fn values(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let map = map_arg(paren, "values", &arguments[0])?.borrow();
+    Ok(Value::list(map.entries().iter().map(|(_, value)| value.clone()).collect()))
+}
+
+fn has(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let map = map_arg(paren, "has", &arguments[0])?.borrow();
+    let found = map.get(&arguments[1]).map_err(|message| runtime_err(paren, &message))?;
+    Ok(Value::Boolean(found.is_some()))
+}
+
+/// Removes a key and returns whether it was there.
+fn delete(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let mut map = map_arg(paren, "delete", &arguments[0])?.borrow_mut();
+    let removed = map.remove(&arguments[1]).map_err(|message| runtime_err(paren, &message))?;
+    Ok(Value::Boolean(removed.is_some()))
+}
+
+fn upper(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    Ok(Value::String(string_arg(paren, "upper", &arguments[0])?.to_uppercase()))
+}
+
+fn lower(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    Ok(Value::String(string_arg(paren, "lower", &arguments[0])?.to_lowercase()))
+}
+
+/// Removes whitespace from both ends.
+fn trim(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    Ok(Value::String(string_arg(paren, "trim", &arguments[0])?.trim().to_string()))
+}
+
+/// The parts of a string between each occurrence of a separator, as a list.
+fn split(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let string = string_arg(paren, "split", &arguments[0])?;
+    let separator = string_arg(paren, "split", &arguments[1])?;
+    if separator.is_empty() {
+        return Err(runtime_err(paren, "split() needs a separator that isn't empty. Use chars() to split into characters."));
+    }
+    Ok(Value::list(string.split(separator.as_str()).map(|part| Value::String(part.to_string())).collect()))
+}
+
+/// The strings in a list, with a separator between each.
+fn join(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let values = list_arg(paren, "join", &arguments[0])?.borrow();
+    let separator = string_arg(paren, "join", &arguments[1])?;
+    let parts = values.iter()
+        .map(|value| match value {
+            Value::String(string) => Ok(string.as_str()),
+            _ => Err(runtime_err(paren, &format!("join() expects a list of strings, not one with {}.", value.type_name())))
+        })
+        .collect::<Result<Vec<&str>, RloxError>>()?;
+    Ok(Value::String(parts.join(separator)))
+}
+
+/// Replaces every occurrence of `from` with `to`.
+fn replace(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let string = string_arg(paren, "replace", &arguments[0])?;
+    let from = string_arg(paren, "replace", &arguments[1])?;
+    let to = string_arg(paren, "replace", &arguments[2])?;
+    if from.is_empty() {
+        return Err(runtime_err(paren, "replace() needs a string to replace that isn't empty."));
+    }
+    Ok(Value::String(string.replace(from.as_str(), to)))
+}
+
+/// The character index of the first occurrence of a substring, or nil if
+/// there isn't one.
+fn find(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let string = string_arg(paren, "find", &arguments[0])?;
+    let needle = string_arg(paren, "find", &arguments[1])?;
+    Ok(match string.find(needle.as_str()) {
+        Some(byte) => Value::Int(i64::try_from(string[..byte].chars().count()).unwrap_or(i64::MAX)),
+        None => Value::Nil
+    })
+}
+
+fn starts_with(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let string = string_arg(paren, "starts_with", &arguments[0])?;
+    let prefix = string_arg(paren, "starts_with", &arguments[1])?;
+    Ok(Value::Boolean(string.starts_with(prefix.as_str())))
+}
+
+fn ends_with(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let string = string_arg(paren, "ends_with", &arguments[0])?;
+    let suffix = string_arg(paren, "ends_with", &arguments[1])?;
+    Ok(Value::Boolean(string.ends_with(suffix.as_str())))
+}
+
+fn repeat(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let string = string_arg(paren, "repeat", &arguments[0])?;
+    let count = int_arg(paren, "repeat", &arguments[1])?;
+    let count = usize::try_from(count)
+        .map_err(|_| runtime_err(paren, &format!("repeat() needs a count of at least 0, not {}.", count)))?;
+    if string.len().checked_mul(count).filter(|len| *len <= MAX_STRING_LEN).is_none() {
+        return Err(runtime_err(paren, "repeat() would make a string that's too long."));
+    }
+    Ok(Value::String(string.repeat(count)))
+}
+
+/// The characters of a string as a list of one character strings.
+fn chars(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let string = string_arg(paren, "chars", &arguments[0])?;
+    Ok(Value::list(string.chars().map(|c| Value::String(c.to_string())).collect()))
+}
+
+/// The number a string spells, written like a number literal with an
+/// optional sign and surrounding whitespace, or nil if it isn't one. So
+/// `num("0xFF")` is 255, and an integer too big for an Int is nil, as its
+/// literal would be an error. Numbers are returned as they are.
+fn num(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let text = match &arguments[0] {
+        Value::String(string) => string.trim(),
+        Value::Int(_) | Value::Number(_) => return Ok(arguments[0].clone()),
+        value => return Err(runtime_err(paren, &format!("num() expects a string or number, not {}.", value.type_name())))
+    };
+    let (negative, literal) = match text.strip_prefix('-') {
+        Some(literal) => (true, literal),
+        None => (false, text.strip_prefix('+').unwrap_or(text))
+    };
+    Ok(match scanner::number_literal(literal) {
+        Some(Literal::Int(n)) => Value::Int(if negative { -n } else { n }),
+        Some(Literal::Number(n)) => Value::Number(if negative { -n } else { n }),
+        _ => Value::Nil
+    })
+}
+
+/// Applies `op` to a number and gives a float.
+fn float(paren: &Token, name: &str, arguments: &[Value], op: fn(f64) -> f64) -> Result<Value, RloxError> {
+    Ok(Value::Number(op(number_arg(paren, name, &arguments[0])?)))
+}
+
+/// Rounds a number to an integer with `op`. Integers are already whole.
+fn integral(paren: &Token, name: &str, arguments: &[Value], op: fn(f64) -> f64) -> Result<Value, RloxError> {
+    let n = match arguments[0] {
+        Value::Int(n) => return Ok(Value::Int(n)),
+        _ => op(number_arg(paren, name, &arguments[0])?)
+    };
+    // Every float from -2^63 up to but not including 2^63 converts exactly.
+    if (-9223372036854775808.0..9223372036854775808.0).contains(&n) {
+        Ok(Value::Int(n as i64))
+    } else {
+        Err(runtime_err(paren, &format!("{}() can't make an integer from {:?}.", name, n)))
+    }
+}
+
+/// The argument that compares `wins` against all the others, or the first
+/// NaN. Integers compare exactly; mixed with floats, they compare as floats.
+fn extreme(paren: &Token, name: &str, arguments: Vec<Value>, wins: Ordering) -> Result<Value, RloxError> {
+    let mut best = arguments[0].clone();
+    number_arg(paren, name, &best)?;
+    for argument in arguments.into_iter().skip(1) {
+        let better = match (&argument, &best) {
+            (Value::Int(l), Value::Int(r)) => l.cmp(r) == wins,
+            _ => {
+                let (l, r) = (number_arg(paren, name, &argument)?, number_arg(paren, name, &best)?);
+                !r.is_nan() && (l.is_nan() || l.partial_cmp(&r) == Some(wins))
+            }
+        };
+        if better {
+            best = argument;
+        }
+    }
+    Ok(best)
+}
+
+fn math_pow(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let x = number_arg(paren, "math.pow", &arguments[0])?;
+    let y = number_arg(paren, "math.pow", &arguments[1])?;
+    Ok(Value::Number(math::pow(x, y)))
+}
+
+fn math_abs(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    match arguments[0] {
+        Value::Int(n) => n.checked_abs().map(Value::Int).ok_or_else(|| runtime_err(paren, "Integer overflow.")),
+        _ => float(paren, "math.abs", &arguments, f64::abs)
+    }
+}
+
+/// The angle of the point (`x`, `y`), taking `y` first like C's `atan2`.
+fn math_atan2(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    let y = number_arg(paren, "math.atan2", &arguments[0])?;
+    let x = number_arg(paren, "math.atan2", &arguments[1])?;
+    Ok(Value::Number(math::atan2(y, x)))
+}
+
+fn math_is_nan(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    Ok(Value::Boolean(number_arg(paren, "math.is_nan", &arguments[0])?.is_nan()))
+}
+
+fn math_is_finite(paren: &Token, arguments: Vec<Value>) -> Result<Value, RloxError> {
+    Ok(Value::Boolean(number_arg(paren, "math.is_finite", &arguments[0])?.is_finite()))
+}
+
+fn list_arg<'a>(paren: &Token, name: &str, value: &'a Value) -> Result<&'a Rc<RefCell<Vec<Value>>>, RloxError> {
+    match value {
+        Value::List(values) => Ok(values),
+        _ => Err(runtime_err(paren, &format!("{}() expects a list, not {}.", name, value.type_name())))
+    }
+}
+
+fn map_arg<'a>(paren: &Token, name: &str, value: &'a Value) -> Result<&'a Rc<RefCell<Map>>, RloxError> {
+    match value {
+        Value::Map(map) => Ok(map),
+        _ => Err(runtime_err(paren, &format!("{}() expects a map, not {}.", name, value.type_name())))
+    }
+}
+
+fn string_arg<'a>(paren: &Token, name: &str, value: &'a Value) -> Result<&'a String, RloxError> {
+    match value {
+        Value::String(string) => Ok(string),
+        _ => Err(runtime_err(paren, &format!("{}() expects a string, not {}.", name, value.type_name())))
+    }
+}
+
+fn number_arg(paren: &Token, name: &str, value: &Value) -> Result<f64, RloxError> {
+    match value {
+        Value::Int(n) => Ok(*n as f64),
+        Value::Number(n) => Ok(*n),
+        _ => Err(runtime_err(paren, &format!("{}() expects a number, not {}.", name, value.type_name())))
+    }
+}
+
+fn int_arg(paren: &Token, name: &str, value: &Value) -> Result<i64, RloxError> {
+    match value {
+        Value::Int(n) => Ok(*n),
+        _ => Err(runtime_err(paren, &format!("{}() expects an integer, not {}.", name, value.type_name())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Value;
+    use crate::interpreter::Interpreter;
+
+    /// The value of the trailing expression in `source`.
+    fn value(source: &str) -> Value {
+        match crate::run(source.to_string(), &mut Interpreter::new()) {
+            Ok(Some(value)) => value,
+            result => panic!("'{}' gave {:?}", source, result)
+        }
+    }
+
+    /// `repr` of the value of the trailing expression in `source`.
+    fn run(source: &str) -> String {
+        value(source).repr()
+    }
+
+    /// The message of the error `source` stops with.
+    fn error(source: &str) -> String {
+        match crate::run(source.to_string(), &mut Interpreter::new()) {
+            Err(errs) => errs[0].message.clone(),
+            Ok(value) => panic!("'{}' gave {:?}", source, value)
+        }
+    }
+
+    #[test]
+    fn list_indexes_are_bounds_checked() {
+        assert_eq!(run("[1, 2, 3][2]"), "3");
+        assert_eq!(error("[1, 2, 3][3]"), "Index 3 is out of bounds for a list of length 3.");
+        assert_eq!(error("[1][-1]"), "Index -1 is out of bounds for a list of length 1.");
+        assert_eq!(error("[][0]"), "Index 0 is out of bounds for a list of length 0.");
+        assert_eq!(error("[1][1.0]"), "List index must be an integer, not Number.");
+    }
+
+    #[test]
+    fn list_index_assignment() {
+        assert_eq!(run("var l = [1, 2];\nl[1] = \"b\";\nl"), "[1, \"b\"]");
+        assert_eq!(run("var l = [[0]];\nl[0][0] = 5;\nl"), "[[5]]");
+        assert_eq!(error("var l = [1];\nl[1] = 2;"), "Index 1 is out of bounds for a list of length 1.");
+    }
+
+    #[test]
+    fn list_built_ins() {
+        assert_eq!(run("var l = [1, 2];\ninsert(l, 2, 3);\ninsert(l, 0, 0);\nl"), "[0, 1, 2, 3]");
+        assert_eq!(error("var l = [1];\ninsert(l, 2, 3);"), "Index 2 is out of bounds for a list of length 1.");
+        assert_eq!(run("var l = [];\ninsert(l, 0, 1);\nl"), "[1]");
+        assert_eq!(run("var l = [1, 2, 3];\n[remove(l, 1), l]"), "[2, [1, 3]]");
+        assert_eq!(run("var l = [1];\npush(l, 2);\n[pop(l), l, len(l)]"), "[2, [1], 1]");
+        assert_eq!(error("pop([])"), "Can't pop from an empty list.");
+        assert_eq!(run("[slice([1, 2, 3], 1), slice([1, 2, 3], 0, 0), contains([1, 2], 2.0)]"), "[[2, 3], [], true]");
+        assert_eq!(error("slice([1, 2], 1, 3)"), "Slice 1..3 is out of bounds for a list of length 2.");
+        assert_eq!(error("push(\"a\", 1)"), "push() expects a list, not String.");
+    }
+
+    #[test]
+    fn map_built_ins() {
+        assert_eq!(run("var m = {1: \"a\", 2: \"b\", 3: \"c\"};\n[delete(m, 2.0), delete(m, 2), keys(m), values(m)]"),
+                   "[true, false, [1, 3], [\"a\", \"c\"]]");
+        assert_eq!(run("var m = {-0.0: 1};\nm[0] = 2;\n[m, has(m, 0.0), has(m, 1)]"), "[{-0.0: 2}, true, false]");
+        assert_eq!(error("has({}, [])"), "A List can't be a map key.");
+        assert_eq!(error("delete({}, 0 / 0.0)"), "NaN can't be a map key.");
+        assert_eq!(error("keys([])"), "keys() expects a map, not List.");
+    }
+
+    #[test]
+    fn strings_are_indexed_by_character() {
+        assert_eq!(run("[slice(\"héllo\", 1, 3), slice(\"héllo\", 4), len(\"héllo\")]"), "[\"él\", \"o\", 5]");
+        assert_eq!(run("[find(\"héllo\", \"l\"), find(\"héllo\", \"é\"), find(\"héllo\", \"x\")]"), "[2, 1, nil]");
+        assert_eq!(error("slice(\"héllo\", 2, 6)"), "Slice 2..6 is out of bounds for a string of length 5.");
+    }
+
+    #[test]
+    fn string_built_ins() {
+        assert_eq!(run("split(\"a,b,,c\", \",\")"), "[\"a\", \"b\", \"\", \"c\"]");
+        assert_eq!(error("split(\"abc\", \"\")"),
+                   "split() needs a separator that isn't empty. Use chars() to split into characters.");
+        assert_eq!(run("[repeat(\"ab\", 3), repeat(\"ab\", 0)]"), "[\"ababab\", \"\"]");
+        assert_eq!(error("repeat(\"ab\", -1)"), "repeat() needs a count of at least 0, not -1.");
+        assert_eq!(run("[join([\"a\", \"b\"], \"-\"), replace(\"aXbX\", \"X\", \"\"), chars(\"hé\")]"),
+                   "[\"a-b\", \"ab\", [\"h\", \"é\"]]");
+        assert_eq!(error("join([\"a\", 1], \"\")"), "join() expects a list of strings, not one with Int.");
+    }
+
+    #[test]
+    fn num_reads_number_literals() {
+        assert_eq!(run("[num(\"0xFF\"), num(\"1_000\"), num(\" -0b101 \"), num(\"+6.02E23\"), num(\"-2.5\")]"),
+                   "[255, 1000, -5, 6.02e23, -2.5]");
+        assert_eq!(run("[num(\"1.0\"), num(7), num(0.5)]"), "[1.0, 7, 0.5]");
+        for text in ["", "-", "x", ".5", "5.", "1__0", "0x", "1e", "- 1", "1 2", "1 // one", "inf", "NaN", "9223372036854775808"] {
+            assert_eq!(run(&format!("num({:?})", text)), "nil", "num({:?})", text);
+        }
+        assert_eq!(error("num([])"), "num() expects a string or number, not List.");
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        for number in ["0", "-12", "9223372036854775807", "0.1", "-2.5", "1e300", "1.0", "5e-324"] {
+            assert_eq!(run(&format!("num(str({0})) == {0}", number)), "true", "{}", number);
+            assert_eq!(run(&format!("type(num(str({0}))) == type({0})", number)), "true", "{}", number);
+        }
+        for source in ["\"a\\\"b\\\\c\\n\"", "[1, \"x\", [nil, true]]", "{\"k\": [2.5]}", "-0.0", "\"\\u{7}\""] {
+            match value(&format!("repr({})", source)) {
+                Value::String(repr) => assert_eq!(run(&repr), run(source)),
+                repr => panic!("repr({}) gave {:?}", source, repr)
+            }
+        }
+        assert_eq!(run("[type(1), type(1.0), type(\"\"), type(nil), type(true), type([]), type({}), type(len)]"),
+                   "[\"Int\", \"Number\", \"String\", \"Nil\", \"Bool\", \"List\", \"Map\", \"Function\"]");
+        assert_eq!(run("[str(\"a\"), str([\"a\"]), str(1.0), bool(0), bool(\"\"), bool(nil)]"),
+                   "[\"a\", \"[\\\"a\\\"]\", \"1.0\", true, true, false]");
+    }
+
+    #[test]
+    fn math_functions() {
+        assert_eq!(error("math.min()"), "math.min() takes at least 1 argument but got 0.");
+        assert_eq!(error("math.sqrt(1, 2)"), "math.sqrt() takes 1 argument but got 2.");
+        assert_eq!(error("math.max(1, \"a\")"), "math.max() expects a number, not String.");
+        assert_eq!(run("[math.min(3, 1.5, 2), math.max(1), math.max(2, 7)]"), "[1.5, 1, 7]");
+        // Halfway cases round away from zero.
+        assert_eq!(run("[math.round(-2.5), math.round(2.5), math.round(-0.4), math.floor(-1.5), math.ceil(1.2)]"),
+                   "[-3, 3, 0, -2, 2]");
+        assert_eq!(run("[math.is_nan(math.sqrt(-1)), math.sqrt(-1) == math.sqrt(-1), math.sqrt(16)]"), "[true, false, 4.0]");
+        assert_eq!(value("repr(math.inf)"), Value::String("math.inf".to_string()));
+        assert_eq!(value("repr([-math.inf])"), Value::String("[-math.inf]".to_string()));
+        assert_eq!(run("[math.is_finite(math.inf), math.inf > 1e308, math.pow(2, 10), math.abs(-3)]"), "[false, true, 1024.0, 3]");
+    }
+}
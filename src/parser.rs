@@ -1,5 +1,5 @@
 use crate::token::{Token, TokenType};
-use crate::grammar::Expr;
+use crate::grammar::{Expr, Stmt};
 use crate::RloxError;
 
 #[derive(Debug, Clone)]
@@ -11,7 +11,7 @@ pub struct Parser {
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Parser {
         Parser {
-            tokens: tokens,
+            tokens,
             current: 0
         }
     }
@@ -19,95 +19,258 @@ impl Parser {
 
 impl Parser {
 
-    pub fn parse(&mut self) -> Result<Expr, RloxError> {
-        self.expression()
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<RloxError>> {
+        let mut statements: Vec<Stmt> = Vec::new();
+        let mut errs: Vec<RloxError> = Vec::new();
+        while !self.is_at_end() {
+            match self.declaration() {
+                Ok(stmt) => statements.push(stmt),
+                Err(err) => {
+                    errs.push(err);
+                    self.synchronize();
+                }
+            }
+        }
+
+        if errs.is_empty() { Ok(statements) } else { Err(errs) }
+    }
+
+    fn declaration(&mut self) -> Result<Stmt, RloxError> {
+        if self.match_toks(vec![TokenType::Var]) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        }
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt, RloxError> {
+        let name = self.consume(TokenType::Identifier, "Expect variable name.")?;
+        let annotation = if self.match_toks(vec![TokenType::Colon]) {
+            Some(self.consume(TokenType::Identifier, "Expect type name after ':'.")?)
+        } else {
+            None
+        };
+        let initializer = if self.match_toks(vec![TokenType::Equal]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(TokenType::Semicolon, "Expect ';' after variable declaration.")?;
+        Ok(Stmt::Var { name, annotation, initializer })
+    }
+
+    fn statement(&mut self) -> Result<Stmt, RloxError> {
+        if self.match_toks(vec![TokenType::Print]) {
+            return self.print_statement();
+        }
+        if self.check(&TokenType::LeftBrace) && !self.at_map_literal() {
+            self.advance();
+            return self.block().map(Stmt::Block);
+        }
+
+        self.expression_statement()
+    }
+
+    /// A `{` that starts a statement opens a block, unless it is followed by
+    /// a single-token key and a `:`, as in `{"a": 1}`, which no block can
+    /// start with. Other maps need parentheses there.
+    fn at_map_literal(&self) -> bool {
+        let after = |n: usize| self.tokens.get(self.current + n).map(|token| token.details().token_type);
+        after(1) != Some(TokenType::RightBrace) && after(2) == Some(TokenType::Colon)
+    }
+
+    fn print_statement(&mut self) -> Result<Stmt, RloxError> {
+        let value = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        Ok(Stmt::Print(value))
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, RloxError> {
+        let mut statements: Vec<Stmt> = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            statements.push(self.declaration()?);
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt, RloxError> {
+        let expr = self.expression()?;
+        if self.is_at_end() {
+            return Ok(Stmt::Echo(expr));
+        }
+
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
+        Ok(Stmt::Expression(expr))
     }
 
     fn expression(&mut self) -> Result<Expr, RloxError> {
-        self.equality()
+        self.comma()
     }
 
-    fn equality(&mut self) -> Result<Expr, RloxError> {
-        self.comparison().and_then(|expr| {
-            let mut expr = expr.clone();
-
-            while self.match_toks(vec![TokenType::BangEqual, TokenType::EqualEqual]) {
-                let clone = self.clone();
-                let operator = clone.previous();
-                match self.comparison() {
-                    Ok(right) => expr = Expr::Binary {left: Box::from(expr), operator: operator.clone(), right: Box::from(right)},
-                    Err(e) => return Err(e)
-                };
-            }
-    
-            Ok(expr)
+    /// The C-style comma operator evaluates both sides and yields the right one.
+    fn comma(&mut self) -> Result<Expr, RloxError> {
+        self.binary(vec![TokenType::Comma], Parser::assignment)
+    }
+
+    fn assignment(&mut self) -> Result<Expr, RloxError> {
+        let expr = self.conditional()?;
+
+        let assignment_tokens = vec![
+            TokenType::Equal,
+            TokenType::PlusEqual,
+            TokenType::MinusEqual,
+            TokenType::StarEqual,
+            TokenType::SlashEqual
+        ];
+        if !self.match_toks(assignment_tokens) {
+            return Ok(expr);
+        }
+
+        let operator = self.previous().clone();
+        let value = Box::from(self.assignment()?);
+        match expr {
+            Expr::Variable(name) => Ok(Expr::Assign { name, operator, value }),
+            Expr::Index { object, bracket, index } => Ok(Expr::SetIndex { object, bracket, index, operator, value }),
+            _ => Err(err_token(&operator, "Invalid assignment target."))
+        }
+    }
+
+    /// `cond ? a : b`. The middle operand may be any expression, and the
+    /// right-associative else branch lets `a ? b : c ? d : e` nest.
+    fn conditional(&mut self) -> Result<Expr, RloxError> {
+        let condition = self.equality()?;
+        if !self.match_toks(vec![TokenType::Question]) {
+            return Ok(condition);
+        }
+
+        let question = self.previous().clone();
+        let then_branch = self.expression()?;
+        self.consume(TokenType::Colon, "Expect ':' after then branch of conditional expression.")?;
+        let else_branch = self.conditional()?;
+        Ok(Expr::Conditional {
+            condition: Box::from(condition),
+            question,
+            then_branch: Box::from(then_branch),
+            else_branch: Box::from(else_branch)
         })
     }
 
+    fn equality(&mut self) -> Result<Expr, RloxError> {
+        self.binary(vec![TokenType::BangEqual, TokenType::EqualEqual], Parser::comparison)
+    }
+
     fn comparison(&mut self) -> Result<Expr, RloxError> {
-        self.addition().and_then(|expr| {
-            let mut expr = expr.clone();
-
-            while self.match_toks(vec![TokenType::Greater, TokenType::GreaterEqual, TokenType::Less, TokenType::LessEqual]) {
-                let clone = self.clone();
-                let operator = clone.previous();
-                match self.addition() {
-                    Ok(right) => expr = Expr::Binary {left: Box::from(expr), operator: operator.clone(), right: Box::from(right)},
-                    Err(e) => return Err(e)
-                };
-            }
-    
-            Ok(expr)
-        })
+        let operators = vec![TokenType::Greater, TokenType::GreaterEqual, TokenType::Less, TokenType::LessEqual];
+        self.binary(operators, Parser::bitwise_or)
+    }
+
+    fn bitwise_or(&mut self) -> Result<Expr, RloxError> {
+        self.binary(vec![TokenType::Pipe], Parser::bitwise_xor)
+    }
+
+    fn bitwise_xor(&mut self) -> Result<Expr, RloxError> {
+        self.binary(vec![TokenType::Caret], Parser::bitwise_and)
+    }
+
+    fn bitwise_and(&mut self) -> Result<Expr, RloxError> {
+        self.binary(vec![TokenType::Ampersand], Parser::shift)
+    }
+
+    fn shift(&mut self) -> Result<Expr, RloxError> {
+        self.binary(vec![TokenType::LessLess, TokenType::GreaterGreater], Parser::addition)
     }
 
     fn addition(&mut self) -> Result<Expr, RloxError> {
-        self.multiplication().and_then(|expr| {
-            let mut expr = expr.clone();
-
-            while self.match_toks(vec![TokenType::Minus, TokenType::Plus]) {
-                let clone = self.clone();
-                let operator = clone.previous();
-                match self.multiplication() {
-                    Ok(right) => expr = Expr::Binary {left: Box::from(expr), operator: operator.clone(), right: Box::from(right)},
-                    Err(e) => return Err(e)
-                };
-            }
-    
-            Ok(expr)
-        })
+        self.binary(vec![TokenType::Minus, TokenType::Plus], Parser::multiplication)
     }
 
     fn multiplication(&mut self) -> Result<Expr, RloxError> {
-        self.unary().and_then(|expr| {
-            let mut expr = expr.clone();
-            while self.match_toks(vec![TokenType::Slash, TokenType::Star]) {
-                let clone = self.clone();
-                let operator = clone.previous();
-                match self.unary() {
-                    Ok(right) => expr = Expr::Binary {left: Box::from(expr), operator: operator.clone(), right: Box::from(right)},
-                    Err(err) => return Err(err)
-                };
-            }
-    
-            return Ok(expr);
-        })
+        self.binary(vec![TokenType::Percent, TokenType::Slash, TokenType::Star], Parser::unary)
+    }
+
+    /// Parses a left-associative chain of `operand (operator operand)*`.
+    fn binary(
+        &mut self,
+        operators: Vec<TokenType>,
+        operand: fn(&mut Parser) -> Result<Expr, RloxError>
+    ) -> Result<Expr, RloxError> {
+        let mut expr = operand(self)?;
+
+        while self.match_toks(operators.clone()) {
+            let operator = self.previous().clone();
+            let right = operand(self)?;
+            expr = Expr::Binary {left: Box::from(expr), operator, right: Box::from(right)};
+        }
+
+        Ok(expr)
     }
 
     fn unary(&mut self) -> Result<Expr, RloxError> {
-        if !self.match_toks(vec![TokenType::Bang, TokenType::Minus]) { 
-            return self.primary();
-        }
-        let clone = self.clone();
-        let operator = clone.previous();
-        self.unary().and_then(|right| {
-            Ok(Expr::Unary { 
-                operator: operator.clone(),
+        if !self.match_toks(vec![TokenType::Bang, TokenType::Minus, TokenType::Tilde]) { 
+            return self.exponent();
+        }
+        let operator = self.previous().clone();
+        self.unary().map(|right| {
+            Expr::Unary { 
+                operator,
                 right: Box::from(right)
-            })
+            }
         })
     }
 
+    /// `**` binds tighter than a unary operator on its left and is right-associative,
+    /// so `-2 ** 2` is `-(2 ** 2)` and `2 ** 3 ** 2` is `2 ** (3 ** 2)`.
+    fn exponent(&mut self) -> Result<Expr, RloxError> {
+        let base = self.call()?;
+        if !self.match_toks(vec![TokenType::StarStar]) {
+            return Ok(base);
+        }
+
+        let operator = self.previous().clone();
+        self.unary().map(|right| {
+            Expr::Binary { left: Box::from(base), operator, right: Box::from(right) }
+        })
+    }
+
+    /// Calls and indexing, which chain left to right as in `f(x)[0]`.
+    fn call(&mut self) -> Result<Expr, RloxError> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.match_toks(vec![TokenType::LeftParen]) {
+                let arguments = self.arguments(TokenType::RightParen)?;
+                let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
+                expr = Expr::Call { callee: Box::from(expr), paren, arguments };
+            } else if self.match_toks(vec![TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                let bracket = self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+                expr = Expr::Index { object: Box::from(expr), bracket, index: Box::from(index) };
+            } else if self.match_toks(vec![TokenType::Dot]) {
+                let name = self.consume(TokenType::Identifier, "Expect member name after '.'.")?;
+                expr = Expr::Get { object: Box::from(expr), name };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Comma-separated expressions up to `close`, which is left for the
+    /// caller. The comma operator isn't allowed in them without parentheses.
+    fn arguments(&mut self, close: TokenType) -> Result<Vec<Expr>, RloxError> {
+        let mut arguments = Vec::new();
+        if self.check(&close) {
+            return Ok(arguments);
+        }
+        loop {
+            arguments.push(self.assignment()?);
+            if !self.match_toks(vec![TokenType::Comma]) {
+                return Ok(arguments);
+            }
+        }
+    }
+
     fn primary(&mut self) -> Result<Expr, RloxError> {
         let literal_tokens = vec![
             TokenType::False,
@@ -123,6 +286,10 @@ impl Parser {
             }
         }
 
+        if self.match_toks(vec![TokenType::Identifier]) {
+            return Ok(Expr::Variable(self.previous().clone()));
+        }
+
         if self.match_toks(vec![TokenType::LeftParen]) {
             return self.expression().and_then(|expr| {
                 match self.consume(TokenType::RightParen, "Expect ')' after expression.") {
@@ -132,8 +299,35 @@ impl Parser {
             });
         }
 
+        if self.match_toks(vec![TokenType::LeftBracket]) {
+            let elements = self.arguments(TokenType::RightBracket)?;
+            self.consume(TokenType::RightBracket, "Expect ']' after list elements.")?;
+            return Ok(Expr::List(elements));
+        }
+
+        if self.match_toks(vec![TokenType::LeftBrace]) {
+            return self.map();
+        }
+
         Err(err_token(self.peek(), "Expect expression."))
     }
+
+    /// The entries of a map literal, after its `{`.
+    fn map(&mut self) -> Result<Expr, RloxError> {
+        let mut entries = Vec::new();
+        if !self.check(&TokenType::RightBrace) {
+            loop {
+                let key = self.assignment()?;
+                self.consume(TokenType::Colon, "Expect ':' after map key.")?;
+                entries.push((key, self.assignment()?));
+                if !self.match_toks(vec![TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        let brace = self.consume(TokenType::RightBrace, "Expect '}' after map entries.")?;
+        Ok(Expr::Map { brace, entries })
+    }
 }
 
 fn err_token(token: &Token, message: &str) -> RloxError {
@@ -149,7 +343,7 @@ fn err_token(token: &Token, message: &str) -> RloxError {
 impl Parser {
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
-            self.current = self.current + 1;
+            self.current += 1;
         }
         self.previous()
     }
@@ -203,8 +397,54 @@ impl Parser {
                 | TokenType::While | TokenType::Print | TokenType::Return => return,
                 _ => ()
             }
+
+            self.advance();
         }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::grammar::Stmt;
 
-        self.advance();
+    /// The parenthesized form of the single statement in `source`.
+    fn tree(source: &str) -> String {
+        let statements: Vec<Stmt> = crate::parse(source.to_string()).unwrap_or_else(|errs| panic!("{:?}", errs));
+        assert_eq!(statements.len(), 1);
+        statements[0].to_string()
+    }
+
+    #[test]
+    fn conditionals_are_right_associative() {
+        assert_eq!(tree("a ? b : c ? d : e"), "(?: a b (?: c d e))");
+        assert_eq!(tree("a ? b ? c : d : e"), "(?: a (?: b c d) e)");
+        assert_eq!(tree("a == b ? c + 1 : d"), "(?: (== a b) (+ c 1) d)");
+    }
+
+    #[test]
+    fn comma_binds_loosest() {
+        assert_eq!(tree("a = 1, b = 2"), "(, (= a 1) (= b 2))");
+        assert_eq!(tree("a, b ? c : d, e"), "(, (, a (?: b c d)) e)");
+        // The middle of a conditional may hold a comma.
+        assert_eq!(tree("a ? b, c : d"), "(?: a (, b c) d)");
+        assert_eq!(tree("f(a, b)"), "(call f a b)");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn conditionals_can_be_assigned() {
+        assert_eq!(tree("a = b ? c : d"), "(= a (?: b c d))");
+        assert_eq!(tree("a += b ? 1 : 2"), "(+= a (?: b 1 2))");
+        assert_eq!(tree("a = b ? c = 1 : (d = 2)"), "(= a (?: b (= c 1) (group (= d 2))))");
+    }
+
+    #[test]
+    fn missing_colon_is_an_error() {
+        let errs = crate::parse("a ? b;".to_string()).err().unwrap();
+        assert_eq!(errs[0].message, "Expect ':' after then branch of conditional expression.");
+    }
+
+    #[test]
+    fn strings_are_printed_as_source() {
+        assert_eq!(tree(r#""a\"b\\c\n""#), r#""a\"b\\c\n""#);
+        assert_eq!(tree(r#"print "\u{1b}";"#), r#"(print "\u{1b}")"#);
+    }
+}
@@ -0,0 +1,462 @@
+use crate::eval::Value;
+use crate::highlight::Highlighter;
+use crate::interpreter::Interpreter;
+use crate::natives::MODULES;
+use crate::scanner::{Scanner, KEYWORDS};
+use crate::syntax;
+use crate::token::{Token, TokenType};
+use crate::{parse, run, report_errors};
+use linefeed::{Completer, Completion, DefaultTerminal, Interface, Prompter, ReadResult, Signal, Terminal};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+const PROMPT: &str = "rlox> ";
+const CONTINUATION_PROMPT: &str = "...> ";
+const HISTORY_SIZE: usize = 1000;
+
+pub fn run_prompt(save_history: bool) {
+    let reader = Interface::new("rlox").expect("Couldn't initialize prompt reader.");
+
+    reader.set_prompt(PROMPT).expect("Couldn't set reader prompt.");
+    reader.set_report_signal(Signal::Interrupt, true);
+
+    let history = if save_history { History::open() } else { History::disabled() };
+    for entry in history.load() {
+        reader.add_history(entry);
+    }
+
+    let mut interpreter = Interpreter::new();
+    let globals = Arc::new(Mutex::new(interpreter.global_names()));
+    reader.set_completer(Arc::new(ReplCompleter { globals: globals.clone() }));
+
+    let highlighter = Highlighter::new();
+    let mut buffer = String::new();
+    loop {
+        let prompt = if buffer.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        match read_line(&reader, &highlighter, prompt).expect("Couldn't read line.") {
+            ReadResult::Input(input) => {
+                buffer.push_str(&input);
+                buffer.push('\n');
+                if buffer.trim_start().starts_with(':') {
+                    run_command(buffer.trim(), &mut interpreter);
+                } else if is_incomplete(&buffer) {
+                    reader.set_prompt(CONTINUATION_PROMPT).expect("Couldn't set reader prompt.");
+                    continue;
+                } else {
+                    execute(buffer.clone(), &mut interpreter);
+                }
+
+                *globals.lock().expect("Completer lock poisoned.") = interpreter.global_names();
+
+                let entry = buffer.trim_end();
+                if !entry.trim().is_empty() {
+                    history.append(entry);
+                    reader.add_history(entry.to_string());
+                }
+            },
+            // Ctrl-C throws away any pending continuation lines.
+            ReadResult::Signal(Signal::Interrupt) => println!("^C"),
+            _ => break
+        }
+
+        buffer.clear();
+        reader.set_prompt(PROMPT).expect("Couldn't set reader prompt.");
+    }
+}
+
+/// Reads a line, repainting it with syntax highlighting after every edit.
+fn read_line(reader: &Interface<DefaultTerminal>, highlighter: &Highlighter, prompt: &str) -> io::Result<ReadResult> {
+    if !highlighter.is_enabled() {
+        return reader.read_line();
+    }
+
+    loop {
+        if let Some(result) = reader.read_line_step(None)? {
+            return Ok(result);
+        }
+        highlighter.redraw(prompt, &reader.buffer());
+    }
+}
+
+/// Runs REPL input, printing a trailing expression's value and binding it
+/// to `_` so the next line can reuse it.
+fn execute(source: String, interpreter: &mut Interpreter) {
+    match run(source, interpreter) {
+        Ok(Some(value)) => {
+            println!("{}", format_value(&value));
+            interpreter.define_global("_", value);
+        },
+        Ok(None) => (),
+        Err(errs) => report_errors(errs)
+    }
+}
+
+/// Formats a result so its type is clear: strings are quoted, and values
+/// whose text reads like something else, such as `inf`, get their type.
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Number(n) if !n.is_finite() => format!("{} ({})", value, value.type_name()),
+        _ => value.repr()
+    }
+}
+
+/// Meta-commands available at the start of a REPL line: name, argument and help text.
+const COMMANDS: &[(&str, &str, &str)] = &[
+    ("help", "", "Show this list of commands"),
+    ("tokens", "<code>", "Show the tokens the scanner produces"),
+    ("ast", "<code>", "Show the syntax tree the parser produces"),
+    ("cst", "<code>", "Show the lossless syntax tree, trivia included"),
+    ("type", "<expr>", "Evaluate an expression and show its runtime type"),
+    ("load", "<file>", "Run a script in the current session"),
+    ("reset", "", "Clear all session state"),
+    ("time", "<code>", "Run code and report how long it took")
+];
+
+fn run_command(line: &str, interpreter: &mut Interpreter) {
+    let line = line.trim_start_matches(':');
+    let (name, argument) = match line.find(char::is_whitespace) {
+        Some(index) => (&line[..index], line[index..].trim()),
+        None => (line, "")
+    };
+
+    match COMMANDS.iter().find(|(command, _, _)| *command == name) {
+        None => {
+            eprintln!("Unknown command ':{}'. Type :help for a list of commands.", name);
+            return;
+        },
+        Some((_, usage, _)) if !usage.is_empty() && argument.is_empty() => {
+            eprintln!("Usage: :{} {}", name, usage);
+            return;
+        },
+        _ => ()
+    }
+
+    match name {
+        "help" => {
+            for (command, usage, description) in COMMANDS {
+                println!("  :{:<14} {}", format!("{} {}", command, usage).trim_end(), description);
+            }
+        },
+        "tokens" => match Scanner::new(argument.to_string()).scan_tokens() {
+            Ok(tokens) => tokens.iter().for_each(print_token),
+            Err(errs) => report_errors(errs)
+        },
+        "ast" => match parse(argument.to_string()) {
+            Ok(statements) => statements.iter().for_each(|stmt| println!("{}", stmt)),
+            Err(errs) => report_errors(errs)
+        },
+        "cst" => print!("{:?}", syntax::parse(argument)),
+        "type" => match parse(argument.to_string()).and_then(|stmts| interpreter.interpret(&stmts).map_err(|e| vec![e])) {
+            Ok(Some(value)) => println!("{}", value.type_name()),
+            Ok(None) => println!("(no value)"),
+            Err(errs) => report_errors(errs)
+        },
+        "load" => match fs::read_to_string(argument) {
+            Ok(contents) => execute(contents, interpreter),
+            Err(err) => eprintln!("Couldn't read '{}': {}", argument, err)
+        },
+        "reset" => {
+            *interpreter = Interpreter::new();
+            println!("Session state cleared.");
+        },
+        "time" => {
+            let start = Instant::now();
+            execute(argument.to_string(), interpreter);
+            println!("Elapsed: {:?}", start.elapsed());
+        },
+        _ => () // unreachable
+    }
+}
+
+fn print_token(token: &Token) {
+    let details = token.details();
+    let token_type = format!("{:?}", details.token_type);
+    match token {
+        Token::Literal(_, literal) => {
+            println!("{:>4}  {:<14} {:<12} {:?}", details.line, token_type, details.lexeme, literal)
+        },
+        Token::NonLiteral(_) => println!("{:>4}  {:<14} {}", details.line, token_type, details.lexeme)
+    }
+}
+
+/// Completes `:command` names at the start of a line, members after a
+/// module name and `.`, and otherwise keywords and the globals defined so
+/// far in the session, built-in functions included.
+struct ReplCompleter {
+    globals: Arc<Mutex<Vec<String>>>
+}
+
+impl<Term: Terminal> Completer<Term> for ReplCompleter {
+    fn complete(&self, word: &str, prompter: &Prompter<Term>, start: usize, _end: usize) -> Option<Vec<Completion>> {
+        let candidates = self.candidates(&prompter.buffer()[..start], word);
+        Some(candidates.into_iter().map(Completion::simple).collect())
+    }
+
+    fn word_start(&self, line: &str, end: usize, _prompter: &Prompter<Term>) -> usize {
+        word_start(line, end)
+    }
+}
+
+impl ReplCompleter {
+    /// The sorted names that can complete `word`, given the text before it.
+    fn candidates(&self, before: &str, word: &str) -> Vec<String> {
+        let module = before.strip_suffix('.').and_then(|before| {
+            MODULES.iter().find(|module| {
+                before.strip_suffix(module.name)
+                    .is_some_and(|rest| !rest.ends_with(|c: char| c == '_' || c.is_alphanumeric()))
+            })
+        });
+
+        let mut candidates: Vec<String> = if let Some(prefix) = word.strip_prefix(':') {
+            COMMANDS.iter()
+                .filter(|(name, _, _)| name.starts_with(prefix))
+                .map(|(name, _, _)| format!(":{}", name))
+                .collect()
+        } else if let Some(module) = module {
+            module.member_names().filter(|name| name.starts_with(word)).map(String::from).collect()
+        } else {
+            let globals = self.globals.lock().expect("Completer lock poisoned.");
+            KEYWORDS.iter().map(|(keyword, _)| keyword.to_string())
+                .chain(globals.iter().cloned())
+                .filter(|name| name.starts_with(word))
+                .collect()
+        };
+
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+}
+
+/// Where the word ending at `end` starts: the identifier characters before
+/// it, plus the colon of a meta-command so only command names are offered.
+fn word_start(line: &str, end: usize) -> usize {
+    let start = line[..end].char_indices().rev()
+        .take_while(|(_, c)| *c == '_' || c.is_alphanumeric())
+        .last()
+        .map_or(end, |(index, _)| index);
+
+    if line[..start].trim_start() == ":" {
+        start - 1
+    } else {
+        start
+    }
+}
+
+/// REPL history kept in `$XDG_STATE_HOME/rlox/history`. Entries are stored
+/// one per line with `\` and newlines escaped, so multi-line input comes
+/// back as a single entry.
+struct History {
+    path: Option<PathBuf>
+}
+
+impl History {
+    fn open() -> History {
+        let state_home = env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")));
+
+        let path = state_home.map(|dir| dir.join("rlox")).and_then(|dir| {
+            fs::create_dir_all(&dir).ok().map(|_| dir.join("history"))
+        });
+        History { path }
+    }
+
+    fn disabled() -> History {
+        History { path: None }
+    }
+
+    /// Reads the saved entries, trimming the file to the newest `HISTORY_SIZE`.
+    fn load(&self) -> Vec<String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Vec::new()
+        };
+        let contents = fs::read_to_string(path).unwrap_or_default();
+        let lines: Vec<&str> = contents.lines().collect();
+        if lines.len() > HISTORY_SIZE {
+            let kept = &lines[lines.len() - HISTORY_SIZE..];
+            fs::write(path, kept.join("\n") + "\n").ok();
+        }
+
+        lines.iter().rev().take(HISTORY_SIZE).rev().map(|line| unescape(line)).collect()
+    }
+
+    fn append(&self, entry: &str) {
+        if let Some(path) = &self.path {
+            // History is a convenience, so failing to write it isn't fatal.
+            OpenOptions::new().create(true).append(true).open(path)
+                .and_then(|mut file| writeln!(file, "{}", escape(entry)))
+                .ok();
+        }
+    }
+}
+
+fn escape(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut entry = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                entry.push('\n');
+                chars.next();
+            },
+            ('\\', Some('\\')) => {
+                entry.push('\\');
+                chars.next();
+            },
+            _ => entry.push(c)
+        }
+    }
+    entry
+}
+
+/// Whether `source` could still become valid with more lines: it ends inside
+/// a string or block comment, has an unclosed `(`, `[` or `{`, or ends in an operator.
+fn is_incomplete(source: &str) -> bool {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = match scanner.scan_tokens() {
+        Ok(tokens) => tokens,
+        Err(_) => return scanner.is_unterminated()
+    };
+
+    let mut depth = 0;
+    for token in &tokens {
+        match token.details().token_type {
+            TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => depth += 1,
+            TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace => depth -= 1,
+            _ => ()
+        }
+    }
+    if depth > 0 {
+        return true;
+    }
+
+    let last = tokens.iter().rev().map(|token| token.details().token_type).find(|t| *t != TokenType::EOF);
+    match last {
+        Some(token_type) => expects_operand(token_type),
+        None => false
+    }
+}
+
+fn expects_operand(token_type: TokenType) -> bool {
+    matches!(token_type,
+        TokenType::Minus | TokenType::Plus | TokenType::Slash | TokenType::Star | TokenType::Percent
+        | TokenType::StarStar | TokenType::Ampersand | TokenType::Pipe | TokenType::Caret | TokenType::Tilde
+        | TokenType::LessLess | TokenType::GreaterGreater | TokenType::Bang | TokenType::BangEqual
+        | TokenType::Equal | TokenType::EqualEqual | TokenType::Greater | TokenType::GreaterEqual
+        | TokenType::Less | TokenType::LessEqual | TokenType::PlusEqual | TokenType::MinusEqual
+        | TokenType::StarEqual | TokenType::SlashEqual | TokenType::Comma | TokenType::Dot
+        | TokenType::Question | TokenType::Colon | TokenType::And | TokenType::Or)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unclosed_input_is_incomplete() {
+        for source in ["(1 +", "[1, [2]", "{\"a\":", "print (1 + 2) *", "var x =", "a ? b :", "\"open", "/* open",
+                       "/* nested /* */", "f(\"a\" ,"] {
+            assert!(is_incomplete(source), "{:?} should continue", source);
+        }
+    }
+
+    #[test]
+    fn finished_input_is_complete() {
+        for source in ["", "1 + 2", "print (1);", "[1, 2]", "\"(\"", "// (", "/* ( */ 1", "1 - -2", "a)",
+                       "1 +* 2;"] {
+            assert!(!is_incomplete(source), "{:?} shouldn't continue", source);
+        }
+    }
+
+    #[test]
+    fn history_entries_round_trip() {
+        for entry in ["print 1;", "var s = \"a\\nb\";", "{\n  print 1;\n}", "\\", "\\n", "a\\\nb", ""] {
+            let line = escape(entry);
+            assert!(!line.contains('\n'), "{:?} was written as {:?}", entry, line);
+            assert_eq!(unescape(&line), entry);
+        }
+        // A lone backslash, as a hand-edited file might have, is kept.
+        assert_eq!(unescape("a\\"), "a\\");
+    }
+
+    #[test]
+    fn history_is_saved_and_trimmed() {
+        let path = env::temp_dir().join(format!("rlox-history-test-{}", std::process::id()));
+        fs::remove_file(&path).ok();
+        let history = History { path: Some(path.clone()) };
+        let entry = "{\n  print \"\\\\\";\n}";
+        history.append(entry);
+        for n in 0..HISTORY_SIZE {
+            history.append(&n.to_string());
+        }
+
+        let entries = history.load();
+        assert_eq!(entries.len(), HISTORY_SIZE);
+        assert_eq!(entries[0], "0");
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), HISTORY_SIZE);
+
+        fs::write(&path, "").unwrap();
+        history.append(entry);
+        assert_eq!(history.load(), vec![entry]);
+        fs::remove_file(&path).ok();
+        assert!(History::disabled().load().is_empty());
+    }
+
+    fn completer(globals: &[&str]) -> ReplCompleter {
+        ReplCompleter { globals: Arc::new(Mutex::new(globals.iter().map(|name| name.to_string()).collect())) }
+    }
+
+    #[test]
+    fn completes_keywords_and_globals() {
+        let completer = completer(&["print_all", "len", "len"]);
+        assert_eq!(completer.candidates("", "pr"), vec!["print", "print_all"]);
+        assert_eq!(completer.candidates("var x = ", "le"), vec!["len"]);
+        assert_eq!(completer.candidates("", "zz"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn completes_commands_and_module_members() {
+        let completer = completer(&["tokens"]);
+        assert_eq!(completer.candidates("", ":t"), vec![":time", ":tokens", ":type"]);
+        assert_eq!(completer.candidates("print math.", "sq"), vec!["sqrt"]);
+        assert!(completer.candidates("math.", "").contains(&"pi".to_string()));
+        // Only a whole module name counts.
+        assert_eq!(completer.candidates("my_math.", "sq"), Vec::<String>::new());
+        assert_eq!(completer.candidates("", "to"), vec!["tokens"]);
+    }
+
+    #[test]
+    fn words_start_after_the_last_non_identifier_character() {
+        assert_eq!(word_start("print foo_b", 11), 6);
+        assert_eq!(word_start("math.sq", 7), 5);
+        assert_eq!(word_start("x = é1", 7), 4);
+        assert_eq!(word_start("1 + ", 4), 4);
+        assert_eq!(word_start(":to", 3), 0);
+        assert_eq!(word_start("  :to", 5), 2);
+        assert_eq!(word_start("a :to", 5), 3);
+    }
+
+    #[test]
+    fn results_are_formatted_with_their_type_clear() {
+        let value = |source: &str| run(source.to_string(), &mut Interpreter::new()).ok().flatten().unwrap();
+        assert_eq!(format_value(&value("\"1\"")), "\"1\"");
+        assert_eq!(format_value(&value("1")), "1");
+        assert_eq!(format_value(&value("1.0")), "1.0");
+        assert_eq!(format_value(&value("nil")), "nil");
+        assert_eq!(format_value(&value("[\"a\\n\", 2]")), "[\"a\\n\", 2]");
+        assert_eq!(format_value(&value("1 / 0.0")), "inf (Number)");
+        assert_eq!(format_value(&value("-1 / 0.0")), "-inf (Number)");
+        assert_eq!(format_value(&value("0 / 0.0")), "NaN (Number)");
+    }
+}
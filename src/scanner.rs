@@ -1,34 +1,47 @@
 use crate::RloxError;
-use crate::token::{TokenType, Literal, Token, TokenDetails};
+use crate::token::{Comment, SourcePiece, TokenType, Literal, Token, TokenDetails};
 
 pub struct Scanner {
     source_code: String,
     tokens: Vec<Token>,
+    comments: Vec<Comment>,
     start: usize,
     current: usize,
-    line: i32
+    line: i32,
+    unterminated: bool
 }
 
-macro_rules! map(
-    { $($key:expr => $value:expr),+ } => {
-        {
-            let mut m = ::std::collections::HashMap::new();
-            $(
-                m.insert($key, $value);
-            )+
-            m
-        }
-    };
-);
+pub const KEYWORDS: &[(&str, TokenType)] = &[
+    ("and", TokenType::And),
+    ("class", TokenType::Class),
+    ("else", TokenType::Else),
+    ("false", TokenType::False),
+    ("for", TokenType::For),
+    ("fun", TokenType::Fun),
+    ("if", TokenType::If),
+    ("nil", TokenType::Nil),
+    ("or", TokenType::Or),
+    ("print", TokenType::Print),
+    ("return", TokenType::Return),
+    ("super", TokenType::Super),
+    ("this", TokenType::This),
+    ("true", TokenType::True),
+    ("var", TokenType::Var),
+    ("while", TokenType::While)
+];
+
+const TOO_LARGE: &str = "Integer literal is too large.";
 
 impl Scanner {
     pub fn new(source_code: String) -> Scanner {
         Scanner {
-            source_code: source_code,
+            source_code,
             tokens: Vec::new(),
+            comments: Vec::new(),
             start: 0,
             current: 0,
-            line: 1
+            line: 1,
+            unterminated: false
         }
     }
 
@@ -36,25 +49,71 @@ impl Scanner {
         let mut errs: Vec<RloxError> = Vec::new();
         while !self.is_at_end() {
             self.start = self.current;
-            self.scan_token().map(|e| errs.push(e));
+            if let Some(e) = self.scan_token() {
+                errs.push(e);
+            }
         }
 
         let eof_token = Token::NonLiteral(
             TokenDetails {
                 token_type: TokenType::EOF,
                 lexeme: String::new(),
-                line: self.line
+                line: self.line,
+                offset: self.current
             }
         );
 
         self.tokens.push(eof_token);
         if errs.is_empty() { Ok(self.tokens.clone()) } else { Err(errs) }
     }
+
+    /// Scans in trivia-preserving mode. Whitespace, comments and text that
+    /// fails to scan are kept as pieces of their own instead of being
+    /// dropped, so nothing from the source is lost. No EOF token is added.
+    pub fn scan_lossless(&mut self) -> Vec<SourcePiece> {
+        let mut pieces: Vec<SourcePiece> = Vec::new();
+        while !self.is_at_end() {
+            self.start = self.current;
+            let token_count = self.tokens.len();
+            let comment_count = self.comments.len();
+            let failed = self.scan_token().is_some();
+
+            let text = self.source_code[self.start..self.current].to_string();
+            let piece = if failed {
+                SourcePiece::Invalid(text)
+            } else if self.tokens.len() > token_count {
+                SourcePiece::Token(self.tokens[token_count].clone())
+            } else if self.comments.len() > comment_count {
+                SourcePiece::Comment(text)
+            } else {
+                // Runs of whitespace are merged into a single piece.
+                if let Some(SourcePiece::Whitespace(previous)) = pieces.last_mut() {
+                    previous.push_str(&text);
+                    continue;
+                }
+                SourcePiece::Whitespace(text)
+            };
+            pieces.push(piece);
+        }
+        pieces
+    }
+
+    /// The comments skipped while scanning, in source order.
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    /// Whether scanning hit the end of input inside a string or block comment.
+    pub fn is_unterminated(&self) -> bool {
+        self.unterminated
+    }
 }
 
 impl Scanner {
-    fn source_nth_char(&self, n: usize) -> char {
-        self.source_code.chars().nth(n).expect("Scanner error.")
+    /// The character starting at byte offset `n`. Offsets always fall on
+    /// character boundaries because the scanner steps a whole char at a time.
+    fn source_char_at(&self, n: usize) -> char {
+        self.source_code[n..].chars().next().expect("Scanner error.")
     }
 
     fn is_at_end(&self) -> bool {
@@ -62,8 +121,9 @@ impl Scanner {
     }
 
     fn advance(&mut self) -> char {
-        self.current = self.current + 1;
-        self.source_nth_char(self.current - 1)
+        let c = self.source_char_at(self.current);
+        self.current += c.len_utf8();
+        c
     }
 
     fn add_non_literal_token(&mut self, token_type: TokenType) {
@@ -73,9 +133,10 @@ impl Scanner {
     fn add_token(&mut self, token_type: TokenType, literal: Option<Literal>) {
         let substring = (self.source_code[self.start..self.current]).to_string();
         let details = TokenDetails {
-            token_type: token_type,
+            token_type,
             lexeme: substring,
-            line: self.line
+            line: self.line,
+            offset: self.start
         };
 
         let token = match literal {
@@ -93,12 +154,44 @@ impl Scanner {
             ')' => self.add_non_literal_token(TokenType::RightParen),
             '{' => self.add_non_literal_token(TokenType::LeftBrace),
             '}' => self.add_non_literal_token(TokenType::RightBrace),
+            '[' => self.add_non_literal_token(TokenType::LeftBracket),
+            ']' => self.add_non_literal_token(TokenType::RightBracket),
             ',' => self.add_non_literal_token(TokenType::Comma),
             '.' => self.add_non_literal_token(TokenType::Dot),
-            '-' => self.add_non_literal_token(TokenType::Minus),
-            '+' => self.add_non_literal_token(TokenType::Plus),
             ';' => self.add_non_literal_token(TokenType::Semicolon),
-            '*' => self.add_non_literal_token(TokenType::Star),
+            '%' => self.add_non_literal_token(TokenType::Percent),
+            '&' => self.add_non_literal_token(TokenType::Ampersand),
+            '|' => self.add_non_literal_token(TokenType::Pipe),
+            '^' => self.add_non_literal_token(TokenType::Caret),
+            '~' => self.add_non_literal_token(TokenType::Tilde),
+            '?' => self.add_non_literal_token(TokenType::Question),
+            ':' => self.add_non_literal_token(TokenType::Colon),
+            '-' => {
+                let token = if self.match_char('=') {
+                    TokenType::MinusEqual
+                } else {
+                    TokenType::Minus
+                };
+                self.add_non_literal_token(token);
+            },
+            '+' => {
+                let token = if self.match_char('=') {
+                    TokenType::PlusEqual
+                } else {
+                    TokenType::Plus
+                };
+                self.add_non_literal_token(token);
+            },
+            '*' => {
+                let token = if self.match_char('*') {
+                    TokenType::StarStar
+                } else if self.match_char('=') {
+                    TokenType::StarEqual
+                } else {
+                    TokenType::Star
+                };
+                self.add_non_literal_token(token);
+            },
             '!' => {
                 let token = if self.match_char('=') {
                     TokenType::BangEqual
@@ -118,6 +211,8 @@ impl Scanner {
             '<' => {
                 let token = if self.match_char('=') {
                     TokenType::LessEqual
+                } else if self.match_char('<') {
+                    TokenType::LessLess
                 } else {
                     TokenType::Less
                 };
@@ -126,6 +221,8 @@ impl Scanner {
             '>' => {
                 let token = if self.match_char('=') {
                     TokenType::GreaterEqual
+                } else if self.match_char('>') {
+                    TokenType::GreaterGreater
                 } else {
                     TokenType::Greater
                 };
@@ -136,16 +233,21 @@ impl Scanner {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                    self.add_comment(self.line);
+                } else if self.match_char('*') {
+                    return self.block_comment();
+                } else if self.match_char('=') {
+                    self.add_non_literal_token(TokenType::SlashEqual);
                 } else {
                     self.add_non_literal_token(TokenType::Slash);
                 }
             },
             ' ' | '\r' | '\t' => (), // whitespace, do nothing
-            '\n' => self.line = self.line + 1, // newline
+            '\n' => self.line += 1, // newline
             '"' => return self.string(),
             _ => {
-                if c.is_digit(10) {
-                    self.number()
+                if c.is_ascii_digit() {
+                    return self.number();
                 } else if is_alphabetic_or_underscore(c) {
                     self.identifier()
                 } else {
@@ -157,63 +259,253 @@ impl Scanner {
     }
 
     fn match_char(&mut self, expected: char) -> bool {
-        if self.is_at_end() || self.source_nth_char(self.current) != expected { 
+        if self.is_at_end() || self.source_char_at(self.current) != expected {
             return false;
         }
 
-        self.current = self.current + 1;
+        self.current += expected.len_utf8();
         true
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() { '\0' } else { self.source_nth_char(self.current) }
+        if self.is_at_end() { '\0' } else { self.source_char_at(self.current) }
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source_code.len() {
-            '\0'
-        } else {
-            self.source_nth_char(self.current + 1)
-        }
+        self.source_code[self.current..].chars().nth(1).unwrap_or('\0')
     }
 
+    /// A string literal. The escapes are `\"`, `\\`, `\n`, `\t`, `\r` and
+    /// `\u{...}` with one to six hex digits, which is everything `quote`
+    /// writes. A backslash used to be an ordinary character, so a string
+    /// like `"C:\path"` that was fine before now has to be `"C:\\path"`;
+    /// the error for any other escape says as much.
     fn string(&mut self) -> Option<RloxError> {
+        let mut value = String::new();
+        let mut invalid = None;
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line = self.line + 1
+            let c = self.advance();
+            if c == '\n' {
+                self.line += 1
+            }
+            if c != '\\' || self.is_at_end() {
+                value.push(c);
+                continue;
+            }
+            match self.advance() {
+                '"' => value.push('"'),
+                '\\' => value.push('\\'),
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                'u' => match self.unicode_escape() {
+                    Ok(c) => value.push(c),
+                    Err(message) => {
+                        invalid.get_or_insert(message);
+                    }
+                },
+                escaped => {
+                    if escaped == '\n' {
+                        self.line += 1
+                    }
+                    let message = format!("Invalid escape '\\{}' in string. Write '\\\\' for a backslash.", escaped);
+                    invalid.get_or_insert(message);
+                }
             }
-            self.advance();
         }
 
         if self.is_at_end() {
+            self.unterminated = true;
             return Some(RloxError::new(self.line, "Unterminated string.", ""));
         }
 
         // The closing ".
         self.advance();
 
-        // Trim the surrounding quotes
-        let val = (self.source_code[(self.start + 1)..(self.current - 1)]).to_string();
-        self.add_token(TokenType::String, Some(Literal::String(val)));
+        if let Some(message) = invalid {
+            return Some(RloxError::new(self.line, &message, ""));
+        }
+        self.add_token(TokenType::String, Some(Literal::String(value)));
         None
     }
 
-    fn number(&mut self) {
-        while self.peek().is_digit(10) {
-            self.advance();
+    /// The character a `\u{...}` escape names, read after the `u`.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        if self.peek() != '{' {
+            return Err("Expect '{' after '\\u' in string.".to_string());
+        }
+        self.advance();
+
+        let mut digits = String::new();
+        while self.peek().is_ascii_hexdigit() {
+            digits.push(self.advance());
+        }
+        if self.peek() != '}' {
+            return Err("Expect '}' after the hex digits of '\\u{'.".to_string());
+        }
+        self.advance();
+
+        u32::from_str_radix(&digits, 16).ok()
+            .filter(|_| digits.len() <= 6)
+            .and_then(char::from_u32)
+            .ok_or_else(|| format!("Invalid escape '\\u{{{}}}' in string; it isn't a Unicode character.", digits))
+    }
+
+    /// Skips a `/* ... */` comment. Block comments nest, so every `/*` needs
+    /// its own matching `*/`.
+    fn block_comment(&mut self) -> Option<RloxError> {
+        let opening_line = self.line;
+        let mut depth = 1;
+
+        while depth > 0 {
+            if self.is_at_end() {
+                self.unterminated = true;
+                return Some(RloxError::new(opening_line, "Unterminated block comment.", " at '/*'"));
+            }
+
+            match self.advance() {
+                '\n' => self.line += 1,
+                '/' if self.match_char('*') => depth += 1,
+                '*' if self.match_char('/') => depth -= 1,
+                _ => ()
+            }
+        }
+
+        self.add_comment(opening_line);
+        None
+    }
+
+    fn add_comment(&mut self, line: i32) {
+        let text = self.source_code[self.start..self.current].to_string();
+        self.comments.push(Comment { text, line, token_index: self.tokens.len() });
+    }
+
+    fn number(&mut self) -> Option<RloxError> {
+        if self.source_char_at(self.start) == '0' {
+            match self.peek() {
+                'x' | 'X' => {
+                    self.advance();
+                    return self.radix_number(16, "hexadecimal");
+                },
+                'b' | 'B' => {
+                    self.advance();
+                    return self.radix_number(2, "binary");
+                },
+                _ => ()
+            }
+        }
+
+        let mut text = self.source_char_at(self.start).to_string();
+        if let Err(err) = self.digits(&mut text, true) {
+            return Some(err);
         }
 
         // Look for a fractional part.
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
-            self.advance();
-            
-            while self.peek().is_digit(10) {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
+            text.push(self.advance());
+            if let Err(err) = self.digits(&mut text, false) {
+                return Some(err);
+            }
+        }
+
+        // Look for an exponent.
+        if self.peek() == 'e' || self.peek() == 'E' {
+            text.push(self.advance());
+            if self.peek() == '+' || self.peek() == '-' {
+                text.push(self.advance());
+            }
+            if !self.peek().is_ascii_digit() {
+                return Some(self.number_error("Expect digits in exponent."));
+            }
+            if let Err(err) = self.digits(&mut text, false) {
+                return Some(err);
+            }
+        }
+
+        if is_alphanumeric_or_underscore(self.peek()) {
+            let message = format!("Unexpected character '{}' in number literal.", self.peek());
+            return Some(self.number_error(&message));
+        }
+
+        // Literals without a fractional part or exponent are integers. One too
+        // big for an integer is an error rather than a float that silently
+        // loses digits, as arithmetic that overflows is.
+        let literal = if text.contains(['.', 'e', 'E']) {
+            text.parse::<f64>().map(Literal::Number).map_err(|_| "Invalid number literal.")
+        } else {
+            text.parse::<i64>().map(Literal::Int).map_err(|_| "Integer literal is too large. Add '.0' to write a float.")
+        };
+
+        match literal {
+            Ok(literal) => {
+                self.add_token(TokenType::Number, Some(literal));
+                None
+            },
+            Err(message) => Some(self.number_error(message))
+        }
+    }
+
+    fn radix_number(&mut self, radix: u32, name: &str) -> Option<RloxError> {
+        let prefix = self.source_code[self.start..self.current].to_string();
+        if !self.peek().is_digit(radix) {
+            let message = if is_alphanumeric_or_underscore(self.peek()) {
+                format!("Invalid digit '{}' in {} literal.", self.peek(), name)
+            } else {
+                format!("Expect digits after '{}'.", prefix)
+            };
+            return Some(self.number_error(&message));
+        }
+
+        let mut text = String::new();
+        if let Err(err) = self.digits_with_radix(&mut text, radix, false) {
+            return Some(err);
+        }
+
+        if is_alphanumeric_or_underscore(self.peek()) {
+            let message = format!("Invalid digit '{}' in {} literal.", self.peek(), name);
+            return Some(self.number_error(&message));
+        }
+
+        match i64::from_str_radix(&text, radix) {
+            Ok(n) => {
+                self.add_token(TokenType::Number, Some(Literal::Int(n)));
+                None
+            },
+            Err(_) => Some(self.number_error(TOO_LARGE))
+        }
+    }
+
+    fn digits(&mut self, text: &mut String, after_digit: bool) -> Result<(), RloxError> {
+        self.digits_with_radix(text, 10, after_digit)
+    }
+
+    /// Consumes a run of digits, allowing single `_` separators between them.
+    /// The digits are appended to `text` with the separators removed.
+    fn digits_with_radix(&mut self, text: &mut String, radix: u32, after_digit: bool) -> Result<(), RloxError> {
+        let mut after_digit = after_digit;
+        loop {
+            let c = self.peek();
+            if c.is_digit(radix) {
+                text.push(self.advance());
+                after_digit = true;
+            } else if c == '_' {
+                if !after_digit || !self.peek_next().is_digit(radix) {
+                    return Err(self.number_error("Digit separator '_' must be between digits."));
+                }
                 self.advance();
+                after_digit = false;
+            } else {
+                return Ok(());
             }
         }
+    }
 
-        let float: f64 = self.source_code[self.start..self.current].parse().expect("Float parsing error.");
-        self.add_token(TokenType::Number, Some(Literal::Number(float)));
+    /// Skips the rest of a malformed number so it is reported only once.
+    fn number_error(&mut self, message: &str) -> RloxError {
+        while is_alphanumeric_or_underscore(self.peek()) {
+            self.advance();
+        }
+        RloxError::new(self.line, message, "")
     }
 
     fn identifier(&mut self) {
@@ -222,25 +514,10 @@ impl Scanner {
         }
 
         let substring = &self.source_code[self.start..self.current];
-        let keyword_map = map!{ 
-            "and" => TokenType::And,
-            "class" => TokenType::Class,
-            "else" => TokenType::Else,
-            "false" => TokenType::False,
-            "for" => TokenType::For,
-            "fun" => TokenType::Fun,
-            "if" => TokenType::If,
-            "nil" => TokenType::Nil,
-            "or" => TokenType::Or,
-            "print" => TokenType::Print,
-            "return" => TokenType::Return,
-            "super" => TokenType::Super,
-            "this" => TokenType::This,
-            "true" => TokenType::True,
-            "var" => TokenType::Var,
-            "while"=> TokenType::While
-        };
-        let token = keyword_map.get(substring).unwrap_or(&TokenType::Identifier);
+        let token = KEYWORDS.iter()
+            .find(|(keyword, _)| *keyword == substring)
+            .map(|(_, token_type)| token_type)
+            .unwrap_or(&TokenType::Identifier);
 
         match token {
             TokenType::Nil => self.add_token(*token, Some(Literal::Nil)),
@@ -251,6 +528,37 @@ impl Scanner {
     }
 }
 
+/// A string literal that scans back to `text`, escaping quotes, backslashes
+/// and control characters.
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", u32::from(c))),
+            _ => quoted.push(c)
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The value of `text` if it's a single number literal and nothing else,
+/// like `0xFF` or `1_000`.
+pub fn number_literal(text: &str) -> Option<Literal> {
+    let tokens = Scanner::new(text.to_string()).scan_tokens().ok()?;
+    match tokens.as_slice() {
+        [Token::Literal(details, literal), _] if details.token_type == TokenType::Number && details.lexeme == text => {
+            Some(literal.clone())
+        },
+        _ => None
+    }
+}
+
 fn is_alphabetic_or_underscore(c: char) -> bool {
     c == '_' || c.is_alphabetic()
 }
@@ -258,3 +566,135 @@ fn is_alphabetic_or_underscore(c: char) -> bool {
 fn is_alphanumeric_or_underscore(c: char) -> bool {
     c == '_' || c.is_alphanumeric()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn literal(source: &str) -> Literal {
+        match Scanner::new(source.to_string()).scan_tokens().expect("Source should scan.").remove(0) {
+            Token::Literal(_, literal) => literal,
+            token => panic!("Expected a literal, got {:?}.", token)
+        }
+    }
+
+    fn error(source: &str) -> String {
+        Scanner::new(source.to_string()).scan_tokens().unwrap_err().remove(0).message
+    }
+
+    /// The line and message of the first error in `source`.
+    fn error_at(source: &str) -> (Option<i32>, String) {
+        let err = Scanner::new(source.to_string()).scan_tokens().unwrap_err().remove(0);
+        (err.line, err.message)
+    }
+
+    #[test]
+    fn number_literals() {
+        assert_eq!(literal("0xFF"), Literal::Int(255));
+        assert_eq!(literal("0XdeadBEEF"), Literal::Int(0xdeadbeef));
+        assert_eq!(literal("0b1010"), Literal::Int(10));
+        assert_eq!(literal("0B1111_0000"), Literal::Int(240));
+        assert_eq!(literal("0xFF_FF"), Literal::Int(65535));
+        assert_eq!(literal("1_000_000"), Literal::Int(1_000_000));
+        assert_eq!(literal("6.02E23"), Literal::Number(6.02e23));
+        assert_eq!(literal("1e3"), Literal::Number(1000.0));
+        assert_eq!(literal("2.5e-3"), Literal::Number(0.0025));
+        assert_eq!(literal("1_0.2_5e+1_0"), Literal::Number(10.25e10));
+        assert_eq!(literal("007"), Literal::Int(7));
+    }
+
+    #[test]
+    fn malformed_number_literals_are_an_error() {
+        let separator = "Digit separator '_' must be between digits.".to_string();
+        assert_eq!(error_at("0x"), (Some(1), "Expect digits after '0x'.".to_string()));
+        assert_eq!(error_at("\n\n1e"), (Some(3), "Expect digits in exponent.".to_string()));
+        assert_eq!(error_at("1e+"), (Some(1), "Expect digits in exponent.".to_string()));
+        assert_eq!(error_at("\n1__0"), (Some(2), separator.clone()));
+        assert_eq!(error_at("1_"), (Some(1), separator.clone()));
+        assert_eq!(error_at("0x_1"), (Some(1), "Invalid digit '_' in hexadecimal literal.".to_string()));
+        assert_eq!(error_at("1.5_"), (Some(1), separator.clone()));
+        assert_eq!(error_at("0b2"), (Some(1), "Invalid digit '2' in binary literal.".to_string()));
+        assert_eq!(error_at("0b10_2"), (Some(1), separator));
+        assert_eq!(error_at("0xFG"), (Some(1), "Invalid digit 'G' in hexadecimal literal.".to_string()));
+        assert_eq!(error_at("12abc"), (Some(1), "Unexpected character 'a' in number literal.".to_string()));
+        // The rest of a bad literal is skipped, so it's reported once.
+        assert_eq!(Scanner::new("1__000_abc + 2".to_string()).scan_tokens().unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn integer_literals_too_big_for_an_int_are_an_error() {
+        assert_eq!(literal("9223372036854775807"), Literal::Int(i64::MAX));
+        assert_eq!(literal("0x7FFF_FFFF_FFFF_FFFF"), Literal::Int(i64::MAX));
+        assert_eq!(error("9223372036854775808"), "Integer literal is too large. Add '.0' to write a float.");
+        assert_eq!(error("0xFFFFFFFFFFFFFFFF"), TOO_LARGE);
+        assert_eq!(error("0b1_0000000000000000000000000000000000000000000000000000000000000000"), TOO_LARGE);
+        // A point or an exponent still makes a float.
+        assert_eq!(literal("9223372036854775808.0"), Literal::Number(9223372036854775808.0));
+        assert_eq!(literal("1e19"), Literal::Number(1e19));
+    }
+
+    /// The lexeme and line of every token before the end.
+    fn tokens(source: &str) -> Vec<(String, i32)> {
+        let tokens = Scanner::new(source.to_string()).scan_tokens().expect("Source should scan.");
+        tokens.iter().map(Token::details)
+            .filter(|details| details.token_type != TokenType::EOF)
+            .map(|details| (details.lexeme, details.line))
+            .collect()
+    }
+
+    #[test]
+    fn block_comments_nest() {
+        assert_eq!(tokens("1 /* a /* b */ still a */ 2"), [("1".to_string(), 1), ("2".to_string(), 1)]);
+        assert_eq!(tokens("/* /* /* deep */ */ */ x"), [("x".to_string(), 1)]);
+        assert_eq!(tokens("/* a * / b / * c */ y"), [("y".to_string(), 1)]);
+    }
+
+    #[test]
+    fn lines_are_counted_across_block_comments() {
+        assert_eq!(tokens("/* one\ntwo\nthree */ x\ny"), [("x".to_string(), 3), ("y".to_string(), 4)]);
+        assert_eq!(tokens("a /* \n /* \n */ \n */ b"), [("a".to_string(), 1), ("b".to_string(), 4)]);
+
+        let mut scanner = Scanner::new("x\n/* first\nline */\ny".to_string());
+        scanner.scan_tokens().unwrap();
+        let comments: Vec<_> = scanner.comments().iter().map(|comment| (comment.text.as_str(), comment.line)).collect();
+        assert_eq!(comments, [("/* first\nline */", 2)]);
+    }
+
+    #[test]
+    fn unterminated_block_comments_are_reported_at_their_opening_line() {
+        let errs = Scanner::new("x\n/* open\n/* nested */\nstill open".to_string()).scan_tokens().unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert_eq!((errs[0].line, errs[0].location.as_str(), errs[0].message.as_str()), (Some(2), " at '/*'", "Unterminated block comment."));
+    }
+
+    #[test]
+    fn string_escapes() {
+        let string = |source| match literal(source) {
+            Literal::String(string) => string,
+            literal => panic!("Expected a string, got {:?}.", literal)
+        };
+        assert_eq!(string(r#""quote \" backslash \\ newline \n tab \t return \r""#), "quote \" backslash \\ newline \n tab \t return \r");
+        assert_eq!(string(r#""\u{41}\u{e9}\u{1F980}\u{0}\u{00000a}""#), "Aé🦀\0\n");
+        assert_eq!(string(r#""C:\\path""#), "C:\\path");
+        assert_eq!(string("\"two\nlines\""), "two\nlines");
+    }
+
+    #[test]
+    fn invalid_string_escapes_are_an_error() {
+        assert_eq!(error(r#""C:\path""#), r"Invalid escape '\p' in string. Write '\\' for a backslash.");
+        assert_eq!(error(r#""\q""#), r"Invalid escape '\q' in string. Write '\\' for a backslash.");
+        assert_eq!(error(r#""\u41""#), r"Expect '{' after '\u' in string.");
+        assert_eq!(error(r#""\u{41""#), r"Expect '}' after the hex digits of '\u{'.");
+        assert_eq!(error(r#""\u{4g}""#), r"Expect '}' after the hex digits of '\u{'.");
+        assert_eq!(error(r#""\u{}""#), r"Invalid escape '\u{}' in string; it isn't a Unicode character.");
+        assert_eq!(error(r#""\u{110000}""#), r"Invalid escape '\u{110000}' in string; it isn't a Unicode character.");
+        assert_eq!(error(r#""\u{D800}""#), r"Invalid escape '\u{D800}' in string; it isn't a Unicode character.");
+        assert_eq!(error(r#""\u{0000041}""#), r"Invalid escape '\u{0000041}' in string; it isn't a Unicode character.");
+        // Only the first bad escape is reported, and scanning carries on after the string.
+        let errs = Scanner::new("\"\\a \\b\" 1 @".to_string()).scan_tokens().unwrap_err();
+        assert_eq!(errs.iter().map(|err| err.message.as_str()).collect::<Vec<_>>(), [
+            r"Invalid escape '\a' in string. Write '\\' for a backslash.",
+            "Unexpected character."
+        ]);
+    }
+}
@@ -0,0 +1,460 @@
+//! Converts the AST to a `Json` tree and back, for `rlox parse --emit` and
+//! `rlox run-ast`. Every node has a `kind` and a `span` of byte offsets, and
+//! tokens keep their lexeme and line so runtime errors still point at the
+//! right place after a round trip.
+
+use crate::grammar::{Expr, Stmt};
+use crate::json::Json;
+use crate::scanner::Scanner;
+use crate::token::{Literal, Token, TokenDetails, TokenType};
+use std::convert::TryFrom;
+
+/// Serializes `statements`, which must have been parsed from `tokens`.
+pub fn to_json(statements: &[Stmt], tokens: &[Token]) -> Json {
+    let mut serializer = Serializer { tokens, cursor: 0 };
+    let statements = statements.iter().map(|stmt| serializer.stmt(stmt)).collect();
+    let end = tokens.last().map_or(0, |eof| eof.details().offset);
+    Json::object(vec![("kind", "Program".into()), ("span", span(0, end)), ("statements", Json::Array(statements))])
+}
+
+/// Walks the AST and the tokens in step, as the parser consumed them, to
+/// find where each node starts and ends.
+struct Serializer<'a> {
+    tokens: &'a [Token],
+    cursor: usize
+}
+
+impl<'a> Serializer<'a> {
+    fn stmt(&mut self, stmt: &Stmt) -> Json {
+        let start = self.start();
+        let (kind, members) = match stmt {
+            Stmt::Block(statements) => {
+                self.skip();
+                let statements = statements.iter().map(|stmt| self.stmt(stmt)).collect();
+                self.skip();
+                ("Block", vec![("statements", Json::Array(statements))])
+            },
+            Stmt::Echo(expr) => ("Echo", vec![("expression", self.expr(expr))]),
+            Stmt::Expression(expr) => {
+                let expr = self.expr(expr);
+                self.skip();
+                ("Expression", vec![("expression", expr)])
+            },
+            Stmt::Print(expr) => {
+                self.skip();
+                let expr = self.expr(expr);
+                self.skip();
+                ("Print", vec![("expression", expr)])
+            },
+            Stmt::Var { name, annotation, initializer } => {
+                self.skip();
+                let name = self.token(name);
+                let annotation = match annotation {
+                    Some(annotation) => {
+                        self.skip();
+                        self.token(annotation)
+                    },
+                    None => Json::Null
+                };
+                let initializer = match initializer {
+                    Some(expr) => {
+                        self.skip();
+                        self.expr(expr)
+                    },
+                    None => Json::Null
+                };
+                self.skip();
+                ("Var", vec![("name", name), ("annotation", annotation), ("initializer", initializer)])
+            }
+        };
+        self.node(kind, start, members)
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Json {
+        let start = self.start();
+        let (kind, members) = match expr {
+            Expr::Assign { name, operator, value } => {
+                let name = self.token(name);
+                let operator = self.token(operator);
+                ("Assign", vec![("name", name), ("operator", operator), ("value", self.expr(value))])
+            },
+            Expr::Binary { left, operator, right } => {
+                let left = self.expr(left);
+                let operator = self.token(operator);
+                ("Binary", vec![("left", left), ("operator", operator), ("right", self.expr(right))])
+            },
+            Expr::Call { callee, paren, arguments } => {
+                let callee = self.expr(callee);
+                let arguments = self.items(arguments);
+                ("Call", vec![("callee", callee), ("arguments", arguments), ("paren", self.token(paren))])
+            },
+            Expr::Conditional { condition, question, then_branch, else_branch } => {
+                let condition = self.expr(condition);
+                let question = self.token(question);
+                let then_branch = self.expr(then_branch);
+                self.skip();
+                let else_branch = self.expr(else_branch);
+                let members = vec![("condition", condition), ("question", question), ("then_branch", then_branch), ("else_branch", else_branch)];
+                ("Conditional", members)
+            },
+            Expr::Get { object, name } => {
+                let object = self.expr(object);
+                self.skip();
+                ("Get", vec![("object", object), ("name", self.token(name))])
+            },
+            Expr::Grouping(inner) => {
+                self.skip();
+                let inner = self.expr(inner);
+                self.skip();
+                ("Grouping", vec![("expression", inner)])
+            },
+            Expr::Index { object, bracket, index } => {
+                let object = self.expr(object);
+                self.skip();
+                let index = self.expr(index);
+                ("Index", vec![("object", object), ("index", index), ("bracket", self.token(bracket))])
+            },
+            Expr::List(elements) => {
+                let elements = self.items(elements);
+                self.skip();
+                ("List", vec![("elements", elements)])
+            },
+            Expr::Map { brace, entries } => {
+                self.skip();
+                let mut items = Vec::new();
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.skip();
+                    }
+                    let key = self.expr(key);
+                    self.skip();
+                    items.push(Json::object(vec![("key", key), ("value", self.expr(value))]));
+                }
+                ("Map", vec![("entries", Json::Array(items)), ("brace", self.token(brace))])
+            },
+            Expr::SetIndex { object, bracket, index, operator, value } => {
+                let object = self.expr(object);
+                self.skip();
+                let index = self.expr(index);
+                let bracket = self.token(bracket);
+                let operator = self.token(operator);
+                ("SetIndex", vec![
+                    ("object", object),
+                    ("index", index),
+                    ("bracket", bracket),
+                    ("operator", operator),
+                    ("value", self.expr(value))
+                ])
+            },
+            Expr::Literal(literal) => {
+                self.skip();
+                let (type_name, value) = literal_json(literal);
+                ("Literal", vec![("type", type_name.into()), ("value", value)])
+            },
+            Expr::Unary { operator, right } => {
+                let operator = self.token(operator);
+                ("Unary", vec![("operator", operator), ("right", self.expr(right))])
+            },
+            Expr::Variable(name) => ("Variable", vec![("name", self.token(name))])
+        };
+        self.node(kind, start, members)
+    }
+
+    /// Serializes comma-separated expressions after an opening bracket,
+    /// leaving the closing bracket to the caller.
+    fn items(&mut self, exprs: &[Expr]) -> Json {
+        self.skip();
+        let mut items = Vec::new();
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.skip();
+            }
+            items.push(self.expr(expr));
+        }
+        Json::Array(items)
+    }
+
+    fn node(&self, kind: &str, start: usize, members: Vec<(&str, Json)>) -> Json {
+        let end = self.cursor.checked_sub(1)
+            .and_then(|last| self.tokens.get(last))
+            .map_or(start, |token| token_end(&token.details()));
+        let mut node = vec![("kind", kind.into()), ("span", span(start, end))];
+        node.extend(members);
+        Json::object(node)
+    }
+
+    fn start(&self) -> usize {
+        self.tokens.get(self.cursor).map_or(0, |token| token.details().offset)
+    }
+
+    fn skip(&mut self) {
+        self.cursor += 1;
+    }
+
+    fn token(&mut self, token: &Token) -> Json {
+        self.skip();
+        let details = token.details();
+        Json::object(vec![
+            ("lexeme", details.lexeme.clone().into()),
+            ("line", Json::Int(details.line.into())),
+            ("span", span(details.offset, token_end(&details)))
+        ])
+    }
+}
+
+fn token_end(details: &TokenDetails) -> usize {
+    details.offset + details.lexeme.len()
+}
+
+fn span(start: usize, end: usize) -> Json {
+    Json::Array(vec![start.into(), end.into()])
+}
+
+/// The type name and value of a literal. JSON has no infinity or NaN, so
+/// those floats are written as strings.
+fn literal_json(literal: &Literal) -> (&'static str, Json) {
+    match literal {
+        Literal::String(string) => ("String", string.clone().into()),
+        Literal::Int(n) => ("Int", Json::Int(*n)),
+        Literal::Number(n) if n.is_finite() => ("Number", Json::Number(*n)),
+        Literal::Number(n) => ("Number", n.to_string().into()),
+        Literal::True => ("Bool", true.into()),
+        Literal::False => ("Bool", false.into()),
+        Literal::Nil => ("Nil", Json::Null)
+    }
+}
+
+/// Rebuilds the statements of a serialized `Program`.
+pub fn from_json(json: &Json) -> Result<Vec<Stmt>, String> {
+    match json.get("kind").as_str() {
+        Some("Program") => statements(json.get("statements")),
+        _ => Err("Expect a Program node at the top.".to_string())
+    }
+}
+
+fn statements(json: &Json) -> Result<Vec<Stmt>, String> {
+    match json {
+        Json::Array(items) => items.iter().map(stmt).collect(),
+        _ => Err("Expect a list of statements.".to_string())
+    }
+}
+
+fn stmt(json: &Json) -> Result<Stmt, String> {
+    match json.get("kind").as_str() {
+        Some("Block") => statements(json.get("statements")).map(Stmt::Block),
+        Some("Echo") => expr(json.get("expression")).map(Stmt::Echo),
+        Some("Expression") => expr(json.get("expression")).map(Stmt::Expression),
+        Some("Print") => expr(json.get("expression")).map(Stmt::Print),
+        Some("Var") => Ok(Stmt::Var {
+            name: token(json.get("name"), &[TokenType::Identifier])?,
+            annotation: optional(json.get("annotation"), |json| token(json, &[TokenType::Identifier]))?,
+            initializer: optional(json.get("initializer"), expr)?
+        }),
+        Some(kind) => Err(format!("Unknown statement kind '{}'.", kind)),
+        None => Err(format!("Expect a statement, got {}.", json))
+    }
+}
+
+const ASSIGNMENT_OPERATORS: &[TokenType] = &[
+    TokenType::Equal,
+    TokenType::PlusEqual,
+    TokenType::MinusEqual,
+    TokenType::StarEqual,
+    TokenType::SlashEqual
+];
+
+const BINARY_OPERATORS: &[TokenType] = &[
+    TokenType::Comma,
+    TokenType::BangEqual,
+    TokenType::EqualEqual,
+    TokenType::Greater,
+    TokenType::GreaterEqual,
+    TokenType::Less,
+    TokenType::LessEqual,
+    TokenType::Pipe,
+    TokenType::Caret,
+    TokenType::Ampersand,
+    TokenType::LessLess,
+    TokenType::GreaterGreater,
+    TokenType::Minus,
+    TokenType::Plus,
+    TokenType::Percent,
+    TokenType::Slash,
+    TokenType::Star,
+    TokenType::StarStar
+];
+
+const UNARY_OPERATORS: &[TokenType] = &[TokenType::Bang, TokenType::Minus, TokenType::Tilde];
+
+fn expr(json: &Json) -> Result<Expr, String> {
+    let child = |key| expr(json.get(key)).map(Box::new);
+    match json.get("kind").as_str() {
+        Some("Assign") => Ok(Expr::Assign {
+            name: token(json.get("name"), &[TokenType::Identifier])?,
+            operator: token(json.get("operator"), ASSIGNMENT_OPERATORS)?,
+            value: child("value")?
+        }),
+        Some("Binary") => Ok(Expr::Binary {
+            left: child("left")?,
+            operator: token(json.get("operator"), BINARY_OPERATORS)?,
+            right: child("right")?
+        }),
+        Some("Call") => Ok(Expr::Call {
+            callee: child("callee")?,
+            paren: token(json.get("paren"), &[TokenType::RightParen])?,
+            arguments: exprs(json.get("arguments"))?
+        }),
+        Some("Conditional") => Ok(Expr::Conditional {
+            condition: child("condition")?,
+            question: token(json.get("question"), &[TokenType::Question])?,
+            then_branch: child("then_branch")?,
+            else_branch: child("else_branch")?
+        }),
+        Some("Get") => Ok(Expr::Get {
+            object: child("object")?,
+            name: token(json.get("name"), &[TokenType::Identifier])?
+        }),
+        Some("Grouping") => child("expression").map(Expr::Grouping),
+        Some("Index") => Ok(Expr::Index {
+            object: child("object")?,
+            bracket: token(json.get("bracket"), &[TokenType::RightBracket])?,
+            index: child("index")?
+        }),
+        Some("List") => exprs(json.get("elements")).map(Expr::List),
+        Some("Literal") => literal(json).map(Expr::Literal),
+        Some("Map") => Ok(Expr::Map {
+            brace: token(json.get("brace"), &[TokenType::RightBrace])?,
+            entries: json.get("entries").as_array().iter()
+                .map(|entry| Ok((expr(entry.get("key"))?, expr(entry.get("value"))?)))
+                .collect::<Result<_, String>>()?
+        }),
+        Some("SetIndex") => Ok(Expr::SetIndex {
+            object: child("object")?,
+            bracket: token(json.get("bracket"), &[TokenType::RightBracket])?,
+            index: child("index")?,
+            operator: token(json.get("operator"), ASSIGNMENT_OPERATORS)?,
+            value: child("value")?
+        }),
+        Some("Unary") => Ok(Expr::Unary {
+            operator: token(json.get("operator"), UNARY_OPERATORS)?,
+            right: child("right")?
+        }),
+        Some("Variable") => token(json.get("name"), &[TokenType::Identifier]).map(Expr::Variable),
+        Some(kind) => Err(format!("Unknown expression kind '{}'.", kind)),
+        None => Err(format!("Expect an expression, got {}.", json))
+    }
+}
+
+fn exprs(json: &Json) -> Result<Vec<Expr>, String> {
+    match json {
+        Json::Array(items) => items.iter().map(expr).collect(),
+        _ => Err(format!("Expect a list of expressions, got {}.", json))
+    }
+}
+
+fn literal(json: &Json) -> Result<Literal, String> {
+    let value = json.get("value");
+    let literal = match (json.get("type").as_str(), value) {
+        (Some("String"), Json::String(string)) => Some(Literal::String(string.clone())),
+        (Some("Int"), Json::Int(n)) => Some(Literal::Int(*n)),
+        (Some("Number"), Json::Int(n)) => Some(Literal::Number(*n as f64)),
+        (Some("Number"), Json::Number(n)) => Some(Literal::Number(*n)),
+        (Some("Number"), Json::String(text)) => text.parse().ok().map(Literal::Number),
+        (Some("Bool"), Json::Bool(true)) => Some(Literal::True),
+        (Some("Bool"), Json::Bool(false)) => Some(Literal::False),
+        (Some("Nil"), Json::Null) => Some(Literal::Nil),
+        _ => None
+    };
+    literal.ok_or_else(|| format!("Invalid literal {}.", json))
+}
+
+fn optional<T>(json: &Json, read: fn(&Json) -> Result<T, String>) -> Result<Option<T>, String> {
+    if json.is_null() { Ok(None) } else { read(json).map(Some) }
+}
+
+/// Rebuilds a token by scanning its lexeme, which must be exactly one
+/// token of one of the `expected` types.
+fn token(json: &Json, expected: &[TokenType]) -> Result<Token, String> {
+    let lexeme = json.get("lexeme").as_str().ok_or_else(|| format!("Expect a token, got {}.", json))?;
+    let scanned = Scanner::new(lexeme.to_string()).scan_tokens().ok().unwrap_or_default();
+    let token = match scanned.as_slice() {
+        [token, _eof] if expected.contains(&token.details().token_type) => token.clone(),
+        _ => return Err(format!("'{}' isn't a valid token here.", lexeme))
+    };
+
+    let line = match json.get("line") {
+        Json::Int(line) => i32::try_from(*line).map_err(|_| format!("Invalid line for '{}'.", lexeme))?,
+        _ => 1
+    };
+    let offset = json.get("span").as_array().first().and_then(Json::as_usize).unwrap_or(0);
+    let with_position = |details: TokenDetails| TokenDetails { line, offset, ..details };
+    Ok(match token {
+        Token::NonLiteral(details) => Token::NonLiteral(with_position(details)),
+        Token::Literal(details, literal) => Token::Literal(with_position(details), literal)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Value;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+    use crate::sexpr;
+
+    fn json(source: &str) -> Json {
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap_or_else(|_| panic!("'{}' doesn't scan", source));
+        let statements = Parser::new(tokens.clone()).parse().unwrap_or_else(|_| panic!("'{}' doesn't parse", source));
+        to_json(&statements, &tokens)
+    }
+
+    fn run_tree(tree: Result<Json, String>) -> Option<Value> {
+        let statements = tree.and_then(|tree| from_json(&tree)).unwrap();
+        Interpreter::new().interpret(&statements).ok().flatten()
+    }
+
+    /// Runs `source` directly and from both printed syntax trees, the way
+    /// `rlox parse` followed by `rlox run-ast` would.
+    fn assert_round_trips(source: &str) {
+        let direct = crate::run(source.to_string(), &mut Interpreter::new()).ok().flatten();
+        assert!(direct.is_some(), "'{}' doesn't run to a value", source);
+        let json = json(source);
+        assert_eq!(run_tree(Json::parse(&json.to_string())), direct, "JSON of '{}'", source);
+        assert_eq!(run_tree(sexpr::parse_sexpr(&sexpr::to_sexpr(&json))), direct, "s-expression of '{}'", source);
+    }
+
+    #[test]
+    fn every_expression_round_trips() {
+        assert_round_trips("var a = 1;\na += 2;\na");
+        assert_round_trips("1 + 2 * 3 - 4 / 5 % 6 << 1 | 8 & 3 ^ 1");
+        assert_round_trips("len(\"abc\")");
+        assert_round_trips("var a = 1;\na > 0 ? \"yes\" : a < 0 ? \"no\" : \"zero\"");
+        assert_round_trips("math.pi");
+        assert_round_trips("(1 + 2) * 3");
+        assert_round_trips("[1, 2, 3][1]");
+        assert_round_trips("[]");
+        assert_round_trips("[1, 1.0, 2.5, 1e300, 1e400, -1e400, 0.1, \"s\", nil, true, false]");
+        assert_round_trips("{\"k\": [1], 2: {}}");
+        assert_round_trips("var m = {\"k\": [1.0, 2]};\nm[\"k\"][0] *= 3;\nm[\"n\"] = -1;\nm");
+        assert_round_trips("var a = 5;\n[-a, !a, ~a, - -a]");
+        assert_round_trips("var a = 1;\nvar b = (a = 3, a + 1);\n[a, b]");
+    }
+
+    #[test]
+    fn every_statement_round_trips() {
+        assert_round_trips("var a: Number = 1.5;\n{ var b; b = a; print b; }\na = a * 2;\na");
+    }
+
+    #[test]
+    fn escaped_strings_round_trip() {
+        assert_round_trips("\"tab\\t quote\\\" backslash\\\\ newline\\n return\\r\"");
+        assert_round_trips("\"naïve 日本語 🦀\"");
+    }
+
+    #[test]
+    fn whole_floats_are_written_as_floats() {
+        let json = json("1.0").to_string();
+        assert!(json.contains(r#""type":"Number","value":1.0"#), "{}", json);
+        assert_eq!(run_tree(Json::parse(&json)), Some(Value::Number(1.0)));
+    }
+}
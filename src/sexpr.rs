@@ -0,0 +1,154 @@
+//! An s-expression spelling of the same `Json` trees `serialize` builds, so
+//! both formats share one serializer. A node `{"kind": "Var", "name": ...}`
+//! is written `(Var :name ...)`, arrays are `[a b]`, and `null` is `nil`.
+
+use crate::json::Json;
+
+pub fn to_sexpr(json: &Json) -> String {
+    match json {
+        Json::Null => "nil".to_string(),
+        Json::Array(items) => {
+            let items: Vec<String> = items.iter().map(to_sexpr).collect();
+            format!("[{}]", items.join(" "))
+        },
+        Json::Object(members) => {
+            let mut parts = Vec::new();
+            for (key, value) in members {
+                match value.as_str() {
+                    Some(kind) if key == "kind" && parts.is_empty() && is_symbol(kind) => parts.push(kind.to_string()),
+                    _ => parts.push(format!(":{} {}", key, to_sexpr(value)))
+                }
+            }
+            format!("({})", parts.join(" "))
+        },
+        // Strings, numbers and booleans are spelled as in JSON.
+        _ => json.to_string()
+    }
+}
+
+fn is_symbol(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic()) && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+pub fn parse_sexpr(text: &str) -> Result<Json, String> {
+    let mut reader = Reader { chars: text.chars().collect(), position: 0 };
+    let value = reader.value()?;
+    reader.skip_whitespace();
+    if reader.position < reader.chars.len() {
+        return Err(reader.error("Unexpected text after s-expression."));
+    }
+    Ok(value)
+}
+
+struct Reader {
+    chars: Vec<char>,
+    position: usize
+}
+
+impl Reader {
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => self.node(),
+            Some('[') => self.list(),
+            Some('"') => self.string(),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let text = self.take_while(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+                Json::parse(&text).map_err(|_| self.error("Invalid number."))
+            },
+            Some(c) if c.is_ascii_alphabetic() => match self.symbol().as_str() {
+                "nil" => Ok(Json::Null),
+                "true" => Ok(Json::Bool(true)),
+                "false" => Ok(Json::Bool(false)),
+                _ => Err(self.error("Unknown symbol."))
+            },
+            Some(_) => Err(self.error("Unexpected character.")),
+            None => Err(self.error("Unexpected end of s-expression."))
+        }
+    }
+
+    /// `(Kind :key value ...)`, where the kind is optional.
+    fn node(&mut self) -> Result<Json, String> {
+        self.position += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            members.push(("kind".to_string(), Json::String(self.symbol())));
+        }
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(')') => {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                },
+                Some(':') => {
+                    self.position += 1;
+                    let key = self.symbol();
+                    if key.is_empty() {
+                        return Err(self.error("Expect a key after ':'."));
+                    }
+                    members.push((key, self.value()?));
+                },
+                _ => return Err(self.error("Expect ':key' or ')'."))
+            }
+        }
+    }
+
+    fn list(&mut self) -> Result<Json, String> {
+        self.position += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(']') {
+                self.position += 1;
+                return Ok(Json::Array(items));
+            }
+            items.push(self.value()?);
+        }
+    }
+
+    /// Strings use JSON escapes, so the JSON parser reads them.
+    fn string(&mut self) -> Result<Json, String> {
+        let start = self.position;
+        self.position += 1;
+        loop {
+            match self.peek() {
+                Some('"') => break,
+                Some('\\') => self.position += 2,
+                Some(_) => self.position += 1,
+                None => return Err(self.error("Unterminated string."))
+            }
+        }
+        self.position += 1;
+        let text: String = self.chars[start..self.position].iter().collect();
+        Json::parse(&text).map_err(|e| self.error(&e))
+    }
+
+    fn symbol(&mut self) -> String {
+        self.take_while(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    fn take_while(&mut self, matches: impl Fn(char) -> bool) -> String {
+        let start = self.position;
+        while self.peek().is_some_and(&matches) {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{} (at character {})", message, self.position)
+    }
+}
@@ -0,0 +1,591 @@
+//! A lossless concrete syntax tree. Unlike the AST, it keeps every byte of
+//! the source, including whitespace, comments and text that doesn't parse,
+//! so tools can map any position back to the tree and rewrite code without
+//! disturbing what they don't touch.
+//!
+//! The tree is built in two layers. Green nodes are immutable and only know
+//! their kind, width and children, so identical subtrees can be shared. Red
+//! nodes (`SyntaxNode`, `SyntaxToken`) wrap a green node with its absolute
+//! offset, and are created on demand while walking down.
+
+use crate::scanner::Scanner;
+use crate::token::{SourcePiece, TokenType};
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyntaxKind {
+    /// A token the parser sees, such as `var` or `+`.
+    Token(TokenType),
+    Whitespace,
+    Comment,
+    /// Text the scanner rejected, such as an unterminated string.
+    Invalid,
+
+    Program,
+    VarDecl,
+    /// `: Type` after a variable name.
+    TypeAnnotation,
+    PrintStmt,
+    ExprStmt,
+    Block,
+    AssignExpr,
+    BinaryExpr,
+    CallExpr,
+    ConditionalExpr,
+    GetExpr,
+    GroupingExpr,
+    IndexExpr,
+    ListExpr,
+    LiteralExpr,
+    MapExpr,
+    UnaryExpr,
+    VariableExpr,
+    /// Tokens that don't fit the grammar where they appear.
+    Error
+}
+
+impl fmt::Display for SyntaxKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyntaxKind::Token(token_type) => write!(f, "{:?}", token_type),
+            kind => write!(f, "{:?}", kind)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct GreenToken {
+    kind: SyntaxKind,
+    text: String
+}
+
+#[derive(Debug)]
+struct GreenNode {
+    kind: SyntaxKind,
+    width: usize,
+    children: Vec<GreenElement>
+}
+
+#[derive(Debug, Clone)]
+enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>)
+}
+
+impl GreenNode {
+    fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> GreenNode {
+        let width = children.iter().map(GreenElement::width).sum();
+        GreenNode { kind, width, children }
+    }
+}
+
+impl GreenElement {
+    fn width(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.width,
+            GreenElement::Token(token) => token.text.len()
+        }
+    }
+}
+
+/// A node positioned in the source. Cloning is cheap.
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    offset: usize
+}
+
+/// A token or trivia positioned in the source.
+#[derive(Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    offset: usize
+}
+
+#[derive(Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken)
+}
+
+impl SyntaxNode {
+    fn new_root(green: GreenNode) -> SyntaxNode {
+        SyntaxNode(Rc::new(NodeData { green: Rc::new(green), offset: 0 }))
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.0.green.kind
+    }
+
+    /// The byte range this node covers in the source.
+    pub fn text_range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.width
+    }
+
+    pub fn children(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        self.0.green.children.iter().map(|child| {
+            let element = match child {
+                GreenElement::Node(green) => {
+                    SyntaxElement::Node(SyntaxNode(Rc::new(NodeData { green: Rc::clone(green), offset })))
+                },
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken { green: Rc::clone(green), offset })
+            };
+            offset += child.width();
+            element
+        }).collect()
+    }
+
+    pub fn child_nodes(&self) -> Vec<SyntaxNode> {
+        self.children().into_iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None
+        }).collect()
+    }
+
+    pub fn child_tokens(&self) -> Vec<SyntaxToken> {
+        self.children().into_iter().filter_map(|child| match child {
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Node(_) => None
+        }).collect()
+    }
+
+    /// Every token and piece of trivia under this node, in source order.
+    pub fn descendant_tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = Vec::new();
+        for child in self.children() {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.descendant_tokens()),
+                SyntaxElement::Token(token) => tokens.push(token)
+            }
+        }
+        tokens
+    }
+
+    /// The source text this node covers, rebuilt from its tokens.
+    pub fn text(&self) -> String {
+        self.descendant_tokens().iter().map(SyntaxToken::text).collect()
+    }
+
+    fn write_tree(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let range = self.text_range();
+        writeln!(f, "{}{}@{}..{}", "  ".repeat(depth), self.kind(), range.start, range.end)?;
+        for child in self.children() {
+            match child {
+                SyntaxElement::Node(node) => node.write_tree(f, depth + 1)?,
+                SyntaxElement::Token(token) => writeln!(f, "{}{:?}", "  ".repeat(depth + 1), token)?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> SyntaxKind {
+        self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn text_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text.len()
+    }
+}
+
+/// Prints the tree one element per line, indented by depth.
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_tree(f, 0)
+    }
+}
+
+impl fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let range = self.text_range();
+        write!(f, "{}@{}..{} {:?}", self.kind(), range.start, range.end, self.text())
+    }
+}
+
+/// Builds the concrete syntax tree for `source`. This never fails: text the
+/// grammar doesn't expect is wrapped in `Error` nodes, so the tree always
+/// reproduces the source byte for byte.
+pub fn parse(source: &str) -> SyntaxNode {
+    let pieces = Scanner::new(source.to_string()).scan_lossless();
+    let mut parser = TreeParser {
+        pieces,
+        position: 0,
+        stack: vec![(SyntaxKind::Program, Vec::new())]
+    };
+
+    while !parser.at(TokenType::EOF) {
+        if parser.at(TokenType::RightBrace) {
+            parser.error_token();
+        } else {
+            parser.declaration();
+        }
+    }
+    parser.eat_trivia();
+
+    let (kind, children) = parser.stack.pop().expect("Syntax tree has no root.");
+    SyntaxNode::new_root(GreenNode::new(kind, children))
+}
+
+/// Binary operators from the loosest to the tightest binding, mirroring
+/// `Parser`. Each level's operands come from the next level down.
+const BINARY_LEVELS: &[&[TokenType]] = &[
+    &[TokenType::BangEqual, TokenType::EqualEqual],
+    &[TokenType::Greater, TokenType::GreaterEqual, TokenType::Less, TokenType::LessEqual],
+    &[TokenType::Pipe],
+    &[TokenType::Caret],
+    &[TokenType::Ampersand],
+    &[TokenType::LessLess, TokenType::GreaterGreater],
+    &[TokenType::Minus, TokenType::Plus],
+    &[TokenType::Percent, TokenType::Slash, TokenType::Star]
+];
+
+const ASSIGNMENT_OPERATORS: &[TokenType] = &[
+    TokenType::Equal,
+    TokenType::PlusEqual,
+    TokenType::MinusEqual,
+    TokenType::StarEqual,
+    TokenType::SlashEqual
+];
+
+/// The same grammar as `Parser`, but recovering from every error in place.
+/// Missing tokens are simply left out of the tree and unexpected ones are
+/// wrapped in an `Error` node. Trivia is attached to whichever node is open
+/// when the next token is reached.
+struct TreeParser {
+    pieces: Vec<SourcePiece>,
+    position: usize,
+    /// The nodes being built, innermost last, with the children so far.
+    stack: Vec<(SyntaxKind, Vec<GreenElement>)>
+}
+
+impl TreeParser {
+    fn declaration(&mut self) {
+        if !self.at(TokenType::Var) {
+            return self.statement();
+        }
+
+        self.start_node(SyntaxKind::VarDecl);
+        self.bump();
+        self.expect(TokenType::Identifier);
+        if self.at(TokenType::Colon) {
+            self.start_node(SyntaxKind::TypeAnnotation);
+            self.bump();
+            self.expect(TokenType::Identifier);
+            self.finish_node();
+        }
+        if self.at(TokenType::Equal) {
+            self.bump();
+            self.expression();
+        }
+        self.expect(TokenType::Semicolon);
+        self.finish_node();
+    }
+
+    fn statement(&mut self) {
+        if self.at(TokenType::LeftBrace) && !self.at_map_literal() {
+            return self.block();
+        }
+
+        let kind = if self.at(TokenType::Print) { SyntaxKind::PrintStmt } else { SyntaxKind::ExprStmt };
+        self.start_node(kind);
+        if kind == SyntaxKind::PrintStmt {
+            self.bump();
+        }
+        self.expression();
+        self.expect(TokenType::Semicolon);
+        self.finish_node();
+    }
+
+    /// Whether a `{` starting a statement opens a map, as `Parser` decides it.
+    fn at_map_literal(&self) -> bool {
+        self.nth(1) != TokenType::RightBrace && self.nth(2) == TokenType::Colon
+    }
+
+    fn block(&mut self) {
+        self.start_node(SyntaxKind::Block);
+        self.bump();
+        while !self.at(TokenType::RightBrace) && !self.at(TokenType::EOF) {
+            self.declaration();
+        }
+        self.expect(TokenType::RightBrace);
+        self.finish_node();
+    }
+
+    fn expression(&mut self) {
+        self.binary(&[TokenType::Comma], TreeParser::assignment);
+    }
+
+    fn assignment(&mut self) {
+        let checkpoint = self.checkpoint();
+        self.conditional();
+        if self.at_any(ASSIGNMENT_OPERATORS) {
+            self.start_node_at(checkpoint, SyntaxKind::AssignExpr);
+            self.bump();
+            self.assignment();
+            self.finish_node();
+        }
+    }
+
+    fn conditional(&mut self) {
+        let checkpoint = self.checkpoint();
+        self.binary_level(0);
+        if self.at(TokenType::Question) {
+            self.start_node_at(checkpoint, SyntaxKind::ConditionalExpr);
+            self.bump();
+            self.expression();
+            self.expect(TokenType::Colon);
+            self.conditional();
+            self.finish_node();
+        }
+    }
+
+    fn binary_level(&mut self, level: usize) {
+        match BINARY_LEVELS.get(level) {
+            Some(operators) => self.binary(operators, |parser| parser.binary_level(level + 1)),
+            None => self.unary()
+        }
+    }
+
+    /// Parses a left-associative chain of `operand (operator operand)*`.
+    fn binary(&mut self, operators: &[TokenType], operand: impl Fn(&mut TreeParser)) {
+        let checkpoint = self.checkpoint();
+        operand(self);
+        while self.at_any(operators) {
+            self.start_node_at(checkpoint, SyntaxKind::BinaryExpr);
+            self.bump();
+            operand(self);
+            self.finish_node();
+        }
+    }
+
+    fn unary(&mut self) {
+        if !self.at_any(&[TokenType::Bang, TokenType::Minus, TokenType::Tilde]) {
+            return self.exponent();
+        }
+
+        self.start_node(SyntaxKind::UnaryExpr);
+        self.bump();
+        self.unary();
+        self.finish_node();
+    }
+
+    fn exponent(&mut self) {
+        let checkpoint = self.checkpoint();
+        self.call();
+        if self.at(TokenType::StarStar) {
+            self.start_node_at(checkpoint, SyntaxKind::BinaryExpr);
+            self.bump();
+            self.unary();
+            self.finish_node();
+        }
+    }
+
+    fn call(&mut self) {
+        let checkpoint = self.checkpoint();
+        self.primary();
+        loop {
+            let kind = match self.peek() {
+                TokenType::LeftParen => SyntaxKind::CallExpr,
+                TokenType::LeftBracket => SyntaxKind::IndexExpr,
+                TokenType::Dot => SyntaxKind::GetExpr,
+                _ => return
+            };
+            self.start_node_at(checkpoint, kind);
+            match kind {
+                SyntaxKind::CallExpr => self.items(TokenType::RightParen),
+                SyntaxKind::IndexExpr => {
+                    self.bump();
+                    self.expression();
+                    self.expect(TokenType::RightBracket);
+                },
+                _ => {
+                    self.bump();
+                    self.expect(TokenType::Identifier);
+                }
+            }
+            self.finish_node();
+        }
+    }
+
+    /// An opening bracket, comma-separated expressions and `close`.
+    fn items(&mut self, close: TokenType) {
+        self.bump();
+        while !self.at(close) {
+            self.assignment();
+            if !self.at(TokenType::Comma) {
+                break;
+            }
+            self.bump();
+        }
+        self.expect(close);
+    }
+
+    fn primary(&mut self) {
+        let kind = match self.peek() {
+            TokenType::False | TokenType::True | TokenType::Nil | TokenType::String | TokenType::Number => {
+                SyntaxKind::LiteralExpr
+            },
+            TokenType::Identifier => SyntaxKind::VariableExpr,
+            TokenType::LeftBracket => {
+                self.start_node(SyntaxKind::ListExpr);
+                self.items(TokenType::RightBracket);
+                return self.finish_node();
+            },
+            TokenType::LeftBrace => {
+                self.start_node(SyntaxKind::MapExpr);
+                self.bump();
+                while !self.at_any(&[TokenType::RightBrace, TokenType::Semicolon, TokenType::EOF]) {
+                    self.assignment();
+                    self.expect(TokenType::Colon);
+                    self.assignment();
+                    if !self.at(TokenType::Comma) {
+                        break;
+                    }
+                    self.bump();
+                }
+                self.expect(TokenType::RightBrace);
+                return self.finish_node();
+            },
+            TokenType::LeftParen => {
+                self.start_node(SyntaxKind::GroupingExpr);
+                self.bump();
+                self.expression();
+                self.expect(TokenType::RightParen);
+                return self.finish_node();
+            },
+            // The expression is missing; whatever follows belongs to an
+            // enclosing rule.
+            TokenType::Semicolon | TokenType::RightBrace | TokenType::EOF => return,
+            _ => return self.error_token()
+        };
+
+        self.start_node(kind);
+        self.bump();
+        self.finish_node();
+    }
+}
+
+impl TreeParser {
+    /// The type of the next token, skipping trivia.
+    fn peek(&self) -> TokenType {
+        self.nth(0)
+    }
+
+    /// The type of the token `n` tokens ahead, skipping trivia.
+    fn nth(&self, n: usize) -> TokenType {
+        self.pieces[self.position..].iter()
+            .filter_map(|piece| match piece {
+                SourcePiece::Token(token) => Some(token.details().token_type),
+                _ => None
+            })
+            .nth(n)
+            .unwrap_or(TokenType::EOF)
+    }
+
+    fn at(&self, token_type: TokenType) -> bool {
+        self.peek() == token_type
+    }
+
+    fn at_any(&self, token_types: &[TokenType]) -> bool {
+        token_types.contains(&self.peek())
+    }
+
+    fn expect(&mut self, token_type: TokenType) {
+        if self.at(token_type) {
+            self.bump();
+        }
+    }
+
+    /// Adds the next token, and any trivia before it, to the current node.
+    fn bump(&mut self) {
+        self.eat_trivia();
+        if let Some(piece) = self.pieces.get(self.position) {
+            let kind = match piece {
+                SourcePiece::Token(token) => SyntaxKind::Token(token.details().token_type),
+                _ => return
+            };
+            let text = piece.text();
+            self.position += 1;
+            self.push(GreenElement::Token(Rc::new(GreenToken { kind, text })));
+        }
+    }
+
+    fn eat_trivia(&mut self) {
+        while let Some(piece) = self.pieces.get(self.position) {
+            let kind = match piece {
+                SourcePiece::Token(_) => return,
+                SourcePiece::Whitespace(_) => SyntaxKind::Whitespace,
+                SourcePiece::Comment(_) => SyntaxKind::Comment,
+                SourcePiece::Invalid(_) => SyntaxKind::Invalid
+            };
+            let text = piece.text();
+            self.position += 1;
+            self.push(GreenElement::Token(Rc::new(GreenToken { kind, text })));
+        }
+    }
+
+    fn error_token(&mut self) {
+        self.start_node(SyntaxKind::Error);
+        self.bump();
+        self.finish_node();
+    }
+
+    fn push(&mut self, element: GreenElement) {
+        self.stack.last_mut().expect("Syntax tree has no open node.").1.push(element);
+    }
+
+    /// Leading trivia stays outside the new node.
+    fn start_node(&mut self, kind: SyntaxKind) {
+        self.eat_trivia();
+        self.stack.push((kind, Vec::new()));
+    }
+
+    /// Marks where a node may start once we know it is needed, as with the
+    /// left operand of a binary expression.
+    fn checkpoint(&mut self) -> usize {
+        self.eat_trivia();
+        self.stack.last().map_or(0, |(_, children)| children.len())
+    }
+
+    /// Starts a node that takes over the current node's children from `checkpoint` on.
+    fn start_node_at(&mut self, checkpoint: usize, kind: SyntaxKind) {
+        let children = self.stack.last_mut().expect("Syntax tree has no open node.").1.split_off(checkpoint);
+        self.stack.push((kind, children));
+    }
+
+    fn finish_node(&mut self) {
+        let (kind, children) = self.stack.pop().expect("Syntax tree has no open node.");
+        self.push(GreenElement::Node(Rc::new(GreenNode::new(kind, children))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_lossless(source: &str) {
+        assert_eq!(parse(source).text(), source);
+    }
+
+    #[test]
+    fn tree_text_reproduces_the_source() {
+        assert_lossless("");
+        assert_lossless("var a = 1; // trailing\n  print a;\n");
+        assert_lossless("/* outer /* nested */ still outer */ print 1;");
+        assert_lossless("print 1 @ 2; # $");
+        assert_lossless("print \"unterminated;\nvar b = 2;");
+        assert_lossless("print \"a\\\"b\\n\";");
+        assert_lossless("var café = \"naïve – 日本語 🦀\"; // ünïcode");
+        assert_lossless("{ if (true) print [1, {\"k\": 2}][0]; else { var x = -(1 ? 2 : 3); } \r\n}");
+    }
+}
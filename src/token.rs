@@ -19,12 +19,45 @@ impl Token {
 pub struct TokenDetails {
     pub token_type: TokenType,
     pub lexeme: String,
-    pub line: i32
+    pub line: i32,
+    /// Byte offset of the lexeme in the source.
+    pub offset: usize
+}
+
+/// A comment the scanner skipped. `token_index` is the index of the token
+/// that follows it, which places it relative to the token stream.
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub text: String,
+    pub line: i32,
+    pub token_index: usize
+}
+
+/// One piece of source text from `Scanner::scan_lossless`. Joining the text
+/// of every piece in order gives back the original source exactly.
+#[derive(Debug, Clone)]
+pub enum SourcePiece {
+    Token(Token),
+    Whitespace(String),
+    Comment(String),
+    /// Text the scanner rejected, such as a stray character or an
+    /// unterminated string.
+    Invalid(String)
+}
+
+impl SourcePiece {
+    pub fn text(&self) -> String {
+        match self {
+            SourcePiece::Token(token) => token.details().lexeme,
+            SourcePiece::Whitespace(text) | SourcePiece::Comment(text) | SourcePiece::Invalid(text) => text.clone()
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
     String(String),
+    Int(i64),
     Number(f64),
     Nil,
     True,
@@ -35,10 +68,8 @@ impl fmt::Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let string = match self {
             Literal::String(string) => string.to_owned(),
-            Literal::Number(n) => {
-                let num = n;
-                format!("{}", num)
-            },
+            Literal::Int(n) => format!("{}", n),
+            Literal::Number(n) => format!("{:?}", n),
             Literal::Nil => "nil".to_owned(),
             Literal::True => "true".to_owned(),
             Literal::False => "false".to_owned()
@@ -47,15 +78,18 @@ impl fmt::Display for Literal {
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenType {
     // Single-character tokens.           
-    LeftParen, RightParen, LeftBrace, RightBrace,
-    Comma, Dot, Minus, Plus, Semicolon, Slash, Star,           
+    LeftParen, RightParen, LeftBrace, RightBrace, LeftBracket, RightBracket,
+    Comma, Dot, Minus, Percent, Plus, Semicolon, Slash, Star,           
+    Ampersand, Pipe, Caret, Tilde, Question, Colon,
 
     // One or two character tokens.     
     Bang, BangEqual, Equal, EqualEqual,
-    Greater, GreaterEqual, Less, LessEqual, 
+    Greater, GreaterEqual, GreaterGreater, Less, LessEqual, LessLess,
+    MinusEqual, PlusEqual, SlashEqual, StarEqual, StarStar,
 
     // Literals.                                     
     Identifier, String, Number,
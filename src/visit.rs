@@ -0,0 +1,382 @@
+//! Traversals over the AST. `Visitor` reads a tree, `VisitorMut` changes it
+//! in place, and `Folder` rebuilds it. Each has a method per node kind
+//! whose default walks into the node's children, so a pass only overrides
+//! the nodes it cares about, and only the `walk_*` functions here need to
+//! change when a node kind is added. Only `Visitor` walks statements so
+//! far; the other two start from an expression.
+
+use crate::grammar::{Expr, Stmt};
+use crate::token::{Literal, Token};
+
+pub trait Visitor {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) {
+        statements.iter().for_each(|stmt| self.visit_stmt(stmt));
+    }
+
+    fn visit_var(&mut self, _name: &Token, _annotation: Option<&Token>, initializer: Option<&Expr>) {
+        if let Some(expr) = initializer {
+            self.visit_expr(expr);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_assign(&mut self, _name: &Token, _operator: &Token, value: &Expr) {
+        self.visit_expr(value);
+    }
+
+    fn visit_binary(&mut self, left: &Expr, _operator: &Token, right: &Expr) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+
+    fn visit_call(&mut self, callee: &Expr, _paren: &Token, arguments: &[Expr]) {
+        self.visit_expr(callee);
+        arguments.iter().for_each(|argument| self.visit_expr(argument));
+    }
+
+    fn visit_conditional(&mut self, condition: &Expr, _question: &Token, then_branch: &Expr, else_branch: &Expr) {
+        self.visit_expr(condition);
+        self.visit_expr(then_branch);
+        self.visit_expr(else_branch);
+    }
+
+    fn visit_get(&mut self, object: &Expr, _name: &Token) {
+        self.visit_expr(object);
+    }
+
+    fn visit_grouping(&mut self, inner: &Expr) {
+        self.visit_expr(inner);
+    }
+
+    fn visit_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr) {
+        self.visit_expr(object);
+        self.visit_expr(index);
+    }
+
+    fn visit_list(&mut self, elements: &[Expr]) {
+        elements.iter().for_each(|element| self.visit_expr(element));
+    }
+
+    fn visit_literal(&mut self, _literal: &Literal) {}
+
+    fn visit_map(&mut self, _brace: &Token, entries: &[(Expr, Expr)]) {
+        for (key, value) in entries {
+            self.visit_expr(key);
+            self.visit_expr(value);
+        }
+    }
+
+    fn visit_set_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr, _operator: &Token, value: &Expr) {
+        self.visit_expr(object);
+        self.visit_expr(index);
+        self.visit_expr(value);
+    }
+
+    fn visit_unary(&mut self, _operator: &Token, right: &Expr) {
+        self.visit_expr(right);
+    }
+
+    fn visit_variable(&mut self, _name: &Token) {}
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    match stmt {
+        Stmt::Block(statements) => visitor.visit_block(statements),
+        Stmt::Echo(expr) | Stmt::Expression(expr) | Stmt::Print(expr) => visitor.visit_expr(expr),
+        Stmt::Var { name, annotation, initializer } => {
+            visitor.visit_var(name, annotation.as_ref(), initializer.as_ref())
+        }
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Assign { name, operator, value } => visitor.visit_assign(name, operator, value),
+        Expr::Binary { left, operator, right } => visitor.visit_binary(left, operator, right),
+        Expr::Call { callee, paren, arguments } => visitor.visit_call(callee, paren, arguments),
+        Expr::Conditional { condition, question, then_branch, else_branch } => {
+            visitor.visit_conditional(condition, question, then_branch, else_branch)
+        },
+        Expr::Get { object, name } => visitor.visit_get(object, name),
+        Expr::Grouping(inner) => visitor.visit_grouping(inner),
+        Expr::Index { object, bracket, index } => visitor.visit_index(object, bracket, index),
+        Expr::List(elements) => visitor.visit_list(elements),
+        Expr::Literal(literal) => visitor.visit_literal(literal),
+        Expr::Map { brace, entries } => visitor.visit_map(brace, entries),
+        Expr::SetIndex { object, bracket, index, operator, value } => {
+            visitor.visit_set_index(object, bracket, index, operator, value)
+        },
+        Expr::Unary { operator, right } => visitor.visit_unary(operator, right),
+        Expr::Variable(name) => visitor.visit_variable(name)
+    }
+}
+
+/// Like `Visitor`, but with mutable access. Override `visit_expr_mut` to
+/// replace whole nodes, or a variant method to change a node's parts.
+pub trait VisitorMut {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_assign_mut(&mut self, _name: &mut Token, _operator: &mut Token, value: &mut Expr) {
+        self.visit_expr_mut(value);
+    }
+
+    fn visit_binary_mut(&mut self, left: &mut Expr, _operator: &mut Token, right: &mut Expr) {
+        self.visit_expr_mut(left);
+        self.visit_expr_mut(right);
+    }
+
+    fn visit_call_mut(&mut self, callee: &mut Expr, _paren: &mut Token, arguments: &mut Vec<Expr>) {
+        self.visit_expr_mut(callee);
+        arguments.iter_mut().for_each(|argument| self.visit_expr_mut(argument));
+    }
+
+    fn visit_conditional_mut(&mut self, condition: &mut Expr, _question: &mut Token, then_branch: &mut Expr, else_branch: &mut Expr) {
+        self.visit_expr_mut(condition);
+        self.visit_expr_mut(then_branch);
+        self.visit_expr_mut(else_branch);
+    }
+
+    fn visit_get_mut(&mut self, object: &mut Expr, _name: &mut Token) {
+        self.visit_expr_mut(object);
+    }
+
+    fn visit_grouping_mut(&mut self, inner: &mut Expr) {
+        self.visit_expr_mut(inner);
+    }
+
+    fn visit_index_mut(&mut self, object: &mut Expr, _bracket: &mut Token, index: &mut Expr) {
+        self.visit_expr_mut(object);
+        self.visit_expr_mut(index);
+    }
+
+    fn visit_list_mut(&mut self, elements: &mut Vec<Expr>) {
+        elements.iter_mut().for_each(|element| self.visit_expr_mut(element));
+    }
+
+    fn visit_literal_mut(&mut self, _literal: &mut Literal) {}
+
+    fn visit_map_mut(&mut self, _brace: &mut Token, entries: &mut Vec<(Expr, Expr)>) {
+        for (key, value) in entries {
+            self.visit_expr_mut(key);
+            self.visit_expr_mut(value);
+        }
+    }
+
+    fn visit_set_index_mut(
+        &mut self,
+        object: &mut Expr,
+        _bracket: &mut Token,
+        index: &mut Expr,
+        _operator: &mut Token,
+        value: &mut Expr
+    ) {
+        self.visit_expr_mut(object);
+        self.visit_expr_mut(index);
+        self.visit_expr_mut(value);
+    }
+
+    fn visit_unary_mut(&mut self, _operator: &mut Token, right: &mut Expr) {
+        self.visit_expr_mut(right);
+    }
+
+    fn visit_variable_mut(&mut self, _name: &mut Token) {}
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    match expr {
+        Expr::Assign { name, operator, value } => visitor.visit_assign_mut(name, operator, value),
+        Expr::Binary { left, operator, right } => visitor.visit_binary_mut(left, operator, right),
+        Expr::Call { callee, paren, arguments } => visitor.visit_call_mut(callee, paren, arguments),
+        Expr::Conditional { condition, question, then_branch, else_branch } => {
+            visitor.visit_conditional_mut(condition, question, then_branch, else_branch)
+        },
+        Expr::Get { object, name } => visitor.visit_get_mut(object, name),
+        Expr::Grouping(inner) => visitor.visit_grouping_mut(inner),
+        Expr::Index { object, bracket, index } => visitor.visit_index_mut(object, bracket, index),
+        Expr::List(elements) => visitor.visit_list_mut(elements),
+        Expr::Literal(literal) => visitor.visit_literal_mut(literal),
+        Expr::Map { brace, entries } => visitor.visit_map_mut(brace, entries),
+        Expr::SetIndex { object, bracket, index, operator, value } => {
+            visitor.visit_set_index_mut(object, bracket, index, operator, value)
+        },
+        Expr::Unary { operator, right } => visitor.visit_unary_mut(operator, right),
+        Expr::Variable(name) => visitor.visit_variable_mut(name)
+    }
+}
+
+/// Rebuilds a tree by value. The defaults fold a node's children and put
+/// the same kind of node back together around them; an override can
+/// return a different kind of node altogether.
+pub trait Folder {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        walk_expr_fold(self, expr)
+    }
+
+    fn fold_assign(&mut self, name: Token, operator: Token, value: Expr) -> Expr {
+        Expr::Assign { name, operator, value: Box::new(self.fold_expr(value)) }
+    }
+
+    fn fold_binary(&mut self, left: Expr, operator: Token, right: Expr) -> Expr {
+        let left = Box::new(self.fold_expr(left));
+        Expr::Binary { left, operator, right: Box::new(self.fold_expr(right)) }
+    }
+
+    fn fold_call(&mut self, callee: Expr, paren: Token, arguments: Vec<Expr>) -> Expr {
+        let callee = Box::new(self.fold_expr(callee));
+        Expr::Call { callee, paren, arguments: arguments.into_iter().map(|argument| self.fold_expr(argument)).collect() }
+    }
+
+    fn fold_conditional(&mut self, condition: Expr, question: Token, then_branch: Expr, else_branch: Expr) -> Expr {
+        Expr::Conditional {
+            condition: Box::new(self.fold_expr(condition)),
+            question,
+            then_branch: Box::new(self.fold_expr(then_branch)),
+            else_branch: Box::new(self.fold_expr(else_branch))
+        }
+    }
+
+    fn fold_get(&mut self, object: Expr, name: Token) -> Expr {
+        Expr::Get { object: Box::new(self.fold_expr(object)), name }
+    }
+
+    fn fold_grouping(&mut self, inner: Expr) -> Expr {
+        Expr::Grouping(Box::new(self.fold_expr(inner)))
+    }
+
+    fn fold_index(&mut self, object: Expr, bracket: Token, index: Expr) -> Expr {
+        let object = Box::new(self.fold_expr(object));
+        Expr::Index { object, bracket, index: Box::new(self.fold_expr(index)) }
+    }
+
+    fn fold_list(&mut self, elements: Vec<Expr>) -> Expr {
+        Expr::List(elements.into_iter().map(|element| self.fold_expr(element)).collect())
+    }
+
+    fn fold_literal(&mut self, literal: Literal) -> Expr {
+        Expr::Literal(literal)
+    }
+
+    fn fold_map(&mut self, brace: Token, entries: Vec<(Expr, Expr)>) -> Expr {
+        let entries = entries.into_iter().map(|(key, value)| (self.fold_expr(key), self.fold_expr(value))).collect();
+        Expr::Map { brace, entries }
+    }
+
+    fn fold_set_index(&mut self, object: Expr, bracket: Token, index: Expr, operator: Token, value: Expr) -> Expr {
+        let object = Box::new(self.fold_expr(object));
+        let index = Box::new(self.fold_expr(index));
+        Expr::SetIndex { object, bracket, index, operator, value: Box::new(self.fold_expr(value)) }
+    }
+
+    fn fold_unary(&mut self, operator: Token, right: Expr) -> Expr {
+        Expr::Unary { operator, right: Box::new(self.fold_expr(right)) }
+    }
+
+    fn fold_variable(&mut self, name: Token) -> Expr {
+        Expr::Variable(name)
+    }
+}
+
+pub fn walk_expr_fold<F: Folder + ?Sized>(folder: &mut F, expr: Expr) -> Expr {
+    match expr {
+        Expr::Assign { name, operator, value } => folder.fold_assign(name, operator, *value),
+        Expr::Binary { left, operator, right } => folder.fold_binary(*left, operator, *right),
+        Expr::Call { callee, paren, arguments } => folder.fold_call(*callee, paren, arguments),
+        Expr::Conditional { condition, question, then_branch, else_branch } => {
+            folder.fold_conditional(*condition, question, *then_branch, *else_branch)
+        },
+        Expr::Get { object, name } => folder.fold_get(*object, name),
+        Expr::Grouping(inner) => folder.fold_grouping(*inner),
+        Expr::Index { object, bracket, index } => folder.fold_index(*object, bracket, *index),
+        Expr::List(elements) => folder.fold_list(elements),
+        Expr::Literal(literal) => folder.fold_literal(literal),
+        Expr::Map { brace, entries } => folder.fold_map(brace, entries),
+        Expr::SetIndex { object, bracket, index, operator, value } => {
+            folder.fold_set_index(*object, bracket, *index, operator, *value)
+        },
+        Expr::Unary { operator, right } => folder.fold_unary(operator, *right),
+        Expr::Variable(name) => folder.fold_variable(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Uses every kind of expression, each with a literal or variable inside.
+    const EVERY_EXPR: &str = "a = [1 + -1, f(1)[1], (1 ? {1: 1} : (1).b), l[1] += 1];";
+
+    fn expr(source: &str) -> Expr {
+        match crate::parse(source.to_string()).ok().unwrap().remove(0) {
+            Stmt::Expression(expr) => expr,
+            _ => panic!("'{}' isn't an expression statement", source)
+        }
+    }
+
+    /// Adds one to every integer literal.
+    struct Increment;
+
+    impl Folder for Increment {
+        fn fold_literal(&mut self, literal: Literal) -> Expr {
+            match literal {
+                Literal::Int(n) => Expr::Literal(Literal::Int(n + 1)),
+                literal => Expr::Literal(literal)
+            }
+        }
+    }
+
+    #[test]
+    fn folding_one_kind_of_node_reaches_it_inside_every_other() {
+        assert_eq!(expr(EVERY_EXPR).to_string(),
+                   "(= a (list (+ 1 (- 1)) (index (call f 1) 1) (group (?: 1 (map (1 1)) (. (group 1) b))) (+= (index l 1) 1)))");
+        assert_eq!(Increment.fold_expr(expr(EVERY_EXPR)).to_string(),
+                   "(= a (list (+ 2 (- 2)) (index (call f 2) 2) (group (?: 2 (map (2 2)) (. (group 2) b))) (+= (index l 2) 2)))");
+    }
+
+    /// Replaces every variable with `nil`.
+    struct ClearVariables;
+
+    impl VisitorMut for ClearVariables {
+        fn visit_expr_mut(&mut self, expr: &mut Expr) {
+            match expr {
+                Expr::Variable(_) => *expr = Expr::Literal(Literal::Nil),
+                _ => walk_expr_mut(self, expr)
+            }
+        }
+    }
+
+    #[test]
+    fn mutating_visitors_reach_every_node() {
+        let mut tree = expr(EVERY_EXPR);
+        ClearVariables.visit_expr_mut(&mut tree);
+        // `a` is an assignment target, not a variable expression, so it stays.
+        assert_eq!(tree.to_string(),
+                   "(= a (list (+ 1 (- 1)) (index (call nil 1) 1) (group (?: 1 (map (1 1)) (. (group 1) b))) (+= (index nil 1) 1)))");
+    }
+
+    /// Collects the names of the variables read, in the order they're visited.
+    #[derive(Default)]
+    struct Reads(Vec<String>);
+
+    impl Visitor for Reads {
+        fn visit_variable(&mut self, name: &Token) {
+            self.0.push(name.details().lexeme);
+        }
+    }
+
+    #[test]
+    fn visitors_walk_statements_and_expressions_in_order() {
+        let statements = crate::parse("var x = a;\n{ print b; c[d] = e ? f : g; }\nh.i(j)".to_string()).ok().unwrap();
+        let mut reads = Reads::default();
+        statements.iter().for_each(|stmt| reads.visit_stmt(stmt));
+        assert_eq!(reads.0, ["a", "b", "c", "d", "e", "f", "g", "h", "j"]);
+    }
+}